@@ -19,7 +19,9 @@ module.exports = async function (provider: anchor.Provider) {
             500,  // 5% protocol fee
             200,   // 2% cancel fee
             30, //0.3& AMM fee
-            new PublicKey("8Nq7eMbvhZiPzZFeYutAoiHqF2uJTZZWwnBRzvkiUUid") //Replace with your wallet's address
+            new PublicKey("8Nq7eMbvhZiPzZFeYutAoiHqF2uJTZZWwnBRzvkiUUid"), //Replace with your wallet's address
+            new PublicKey("8Nq7eMbvhZiPzZFeYutAoiHqF2uJTZZWwnBRzvkiUUid"), // dev fee wallet
+            5000 // 50% of every fee goes to the dev wallet
         )
         .accounts({
             authority: provider.wallet.publicKey,
@@ -0,0 +1,209 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use crate::error::ErrorCode;
+
+/// Metaplex Core program, owner of every Core asset
+pub const MPL_CORE_PROGRAM_ID: Pubkey = pubkey!("CoREENxT6tW1HoK8ypY1SxRMZTcVPm7R94rH4PZNhX7d");
+
+// Instruction discriminators (single byte)
+const CREATE_V2: u8 = 20;
+const UPDATE_PLUGIN_V1: u8 = 6;
+const BURN_V1: u8 = 12;
+
+/// `Key::AssetV1`, first byte of an asset account
+const ASSET_V1_KEY: u8 = 1;
+/// `Plugin::Attributes` variant index
+const ATTRIBUTES_PLUGIN: u8 = 6;
+
+#[derive(AnchorSerialize, Clone, PartialEq, Eq, Debug)]
+pub struct Attribute {
+    pub key: String,
+    pub value: String,
+}
+
+/// Attributes shown by wallets and marketplaces for a position asset.
+pub fn position_attributes(market: &Pubkey, outcome: &str, amount: u64) -> Vec<Attribute> {
+    vec![
+        Attribute { key: "market".to_string(), value: market.to_string() },
+        Attribute { key: "outcome".to_string(), value: outcome.to_string() },
+        Attribute { key: "amount".to_string(), value: amount.to_string() },
+    ]
+}
+
+/// Owner of a Core asset: key (1) | owner (32) | update_authority | name | uri | seq
+pub fn load_asset_owner(asset: &AccountInfo) -> Result<Pubkey> {
+    require_keys_eq!(*asset.owner, MPL_CORE_PROGRAM_ID, ErrorCode::InvalidPositionAsset);
+    let data = asset.try_borrow_data()?;
+    parse_asset_owner(&data)
+}
+
+pub fn parse_asset_owner(data: &[u8]) -> Result<Pubkey> {
+    require!(data.len() >= 33 && data[0] == ASSET_V1_KEY, ErrorCode::InvalidPositionAsset);
+    Ok(Pubkey::new_from_array(data[1..33].try_into().unwrap()))
+}
+
+/// CreateV2 args: data_state | name | uri | plugins: Option<Vec<(Plugin, Option<Authority>)>> | external adapters: Option<Vec<_>>
+pub fn create_instruction_data(name: &str, uri: &str, attributes: &[Attribute]) -> Result<Vec<u8>> {
+    let mut data = vec![CREATE_V2, 0]; // DataState::AccountState
+    name.serialize(&mut data)?;
+    uri.serialize(&mut data)?;
+    data.extend_from_slice(&[1, 1, 0, 0, 0, ATTRIBUTES_PLUGIN]); // Some(vec![Attributes])
+    attributes.serialize(&mut data)?;
+    data.extend_from_slice(&[0, 0]); // default authority (update authority), no external adapters
+    Ok(data)
+}
+
+pub fn update_attributes_instruction_data(attributes: &[Attribute]) -> Result<Vec<u8>> {
+    let mut data = vec![UPDATE_PLUGIN_V1, ATTRIBUTES_PLUGIN];
+    attributes.serialize(&mut data)?;
+    Ok(data)
+}
+
+/// Mint a Core asset owned by `owner`, with the market PDA as update authority.
+#[allow(clippy::too_many_arguments)]
+pub fn create_position_asset<'info>(
+    mpl_core_program: &AccountInfo<'info>,
+    asset: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    update_authority: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    name: &str,
+    attributes: &[Attribute],
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: MPL_CORE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(asset.key(), true),
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // collection
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // authority (payer)
+            AccountMeta::new(payer.key(), true),
+            AccountMeta::new_readonly(owner.key(), false),
+            AccountMeta::new_readonly(update_authority.key(), false),
+            AccountMeta::new_readonly(system_program.key(), false),
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // log wrapper
+        ],
+        data: create_instruction_data(name, "", attributes)?,
+    };
+    invoke(
+        &ix,
+        &[
+            asset.clone(),
+            mpl_core_program.clone(),
+            payer.clone(),
+            owner.clone(),
+            update_authority.clone(),
+            system_program.clone(),
+        ]
+    )?;
+    Ok(())
+}
+
+/// Rewrite the attributes of a position asset, signed by the market PDA (update authority).
+pub fn update_position_attributes<'info>(
+    mpl_core_program: &AccountInfo<'info>,
+    asset: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    update_authority: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    attributes: &[Attribute],
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: MPL_CORE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(asset.key(), false),
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // collection
+            AccountMeta::new(payer.key(), true),
+            AccountMeta::new_readonly(update_authority.key(), true),
+            AccountMeta::new_readonly(system_program.key(), false),
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // log wrapper
+        ],
+        data: update_attributes_instruction_data(attributes)?,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            asset.clone(),
+            mpl_core_program.clone(),
+            payer.clone(),
+            update_authority.clone(),
+            system_program.clone(),
+        ],
+        signer_seeds
+    )?;
+    Ok(())
+}
+
+/// Burn a position asset once the position is settled, signed by its owner.
+pub fn burn_position_asset<'info>(
+    mpl_core_program: &AccountInfo<'info>,
+    asset: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: MPL_CORE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(asset.key(), false),
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // collection
+            AccountMeta::new(owner.key(), true),                   // payer
+            AccountMeta::new_readonly(owner.key(), true),          // authority
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // system program
+            AccountMeta::new_readonly(MPL_CORE_PROGRAM_ID, false), // log wrapper
+        ],
+        data: vec![BURN_V1, 0], // no compression proof
+    };
+    invoke(&ix, &[asset.clone(), mpl_core_program.clone(), owner.clone()])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_asset_owner() {
+        let owner = Pubkey::new_unique();
+        let mut data = vec![ASSET_V1_KEY];
+        data.extend_from_slice(owner.as_ref());
+        data.extend_from_slice(&[0; 10]); // update authority, name, uri...
+
+        assert_eq!(parse_asset_owner(&data).unwrap(), owner);
+    }
+
+    #[test]
+    fn rejects_non_asset_accounts() {
+        let mut data = vec![5u8]; // CollectionV1
+        data.extend_from_slice(&[1; 32]);
+        assert!(parse_asset_owner(&data).is_err());
+        assert!(parse_asset_owner(&[ASSET_V1_KEY; 20]).is_err());
+    }
+
+    #[test]
+    fn encodes_create_v2_with_attributes() {
+        let attributes = vec![Attribute { key: "k".to_string(), value: "v".to_string() }];
+        let data = create_instruction_data("N", "", &attributes).unwrap();
+
+        assert_eq!(
+            data,
+            vec![
+                20, 0,                  // CreateV2, AccountState
+                1, 0, 0, 0, b'N',       // name
+                0, 0, 0, 0,             // uri
+                1, 1, 0, 0, 0, 6,       // Some([Attributes
+                1, 0, 0, 0,             //   attribute_list len
+                1, 0, 0, 0, b'k',
+                1, 0, 0, 0, b'v',
+                0,                      //   authority: None])
+                0,                      // external adapters: None
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_update_plugin_v1() {
+        let data = update_attributes_instruction_data(&[]).unwrap();
+        assert_eq!(data, vec![6, 6, 0, 0, 0, 0]);
+    }
+}
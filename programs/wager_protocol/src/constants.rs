@@ -1 +1,31 @@
+pub const BPS_DENOMINATOR: u16 = 10_000;
 
+// Market limits
+pub const MIN_OUTCOMES: usize = 2;
+pub const MAX_OUTCOMES: usize = 16;
+pub const MAX_QUESTION_LEN: usize = 200;
+pub const MAX_OUTCOME_LEN: usize = 50;
+
+// Hard upper bounds for the governed fee parameters
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000; // 10%
+pub const MAX_CANCEL_FEE_BPS: u16 = 1_000;   // 10%
+pub const MAX_AMM_FEE_BPS: u16 = 1_000;      // 10%
+
+// Optimistic resolution
+pub const DEFAULT_DISPUTE_WINDOW: i64 = 86_400;     // 24h
+pub const MAX_DISPUTE_WINDOW: i64 = 30 * 86_400;    // 30 days
+
+// Resolver committees
+pub const MAX_RESOLVERS: usize = 7;
+
+// Order books
+pub const MAX_BOOK_ORDERS: usize = 32;   // resting orders per side
+pub const MAX_BOOK_EVENTS: usize = 32;   // fills waiting for the crank
+pub const BOOK_LOT_SIZE: u64 = 10_000;   // order quantities are whole lots so every price in bps divides evenly
+
+// Parlays
+pub const MIN_PARLAY_LEGS: usize = 2;
+pub const MAX_PARLAY_LEGS: usize = 8;
+pub const MAX_PARLAY_LEG_ODDS: u64 = 20_000_000_000; // 20x on PRECISION, a 5% price
+pub const MAX_PARLAY_PRICE_MOVE_BPS: u16 = 500;        // 5% away from the price snapshot
+pub const PRICE_SNAPSHOT_INTERVAL: i64 = 300;          // 5 minutes
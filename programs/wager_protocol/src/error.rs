@@ -43,4 +43,144 @@ pub enum ErrorCode {
     NoWinnersRemaining,
     #[msg("Invalid fee recipient")]
     InvalidFeeRecipient,
-}
\ No newline at end of file
+    #[msg("Fee too high")]
+    FeeTooHigh,
+    #[msg("No pending authority")]
+    NoPendingAuthority,
+    #[msg("Protocol paused")]
+    ProtocolPaused,
+    #[msg("Market paused")]
+    MarketPaused,
+    #[msg("Market in emergency mode")]
+    EmergencyMode,
+    #[msg("Market not in emergency mode")]
+    NotEmergencyMode,
+    #[msg("Invalid fee share")]
+    InvalidFeeShare,
+    #[msg("Missing fee token account")]
+    MissingFeeAccount,
+    #[msg("Question too long")]
+    QuestionTooLong,
+    #[msg("Invalid scalar range")]
+    InvalidScalarRange,
+    #[msg("Invalid market type")]
+    InvalidMarketType,
+    #[msg("Market voided")]
+    MarketVoided,
+    #[msg("Market not voided")]
+    MarketNotVoided,
+    #[msg("Invalid resolution deadline")]
+    InvalidResolutionDeadline,
+    #[msg("Resolution deadline passed")]
+    ResolutionDeadlinePassed,
+    #[msg("Resolution deadline not reached")]
+    ResolutionDeadlineNotReached,
+    #[msg("Invalid mint")]
+    InvalidMint,
+    #[msg("Invalid dispute window")]
+    InvalidDisputeWindow,
+    #[msg("Resolution pending")]
+    ResolutionPending,
+    #[msg("No resolution proposed")]
+    NoResolutionProposed,
+    #[msg("Already disputed")]
+    AlreadyDisputed,
+    #[msg("Dispute window closed")]
+    DisputeWindowClosed,
+    #[msg("Dispute window still open")]
+    DisputeWindowOpen,
+    #[msg("Resolution disputed")]
+    ResolutionDisputed,
+    #[msg("Resolution not disputed")]
+    NotDisputed,
+    #[msg("Invalid resolution source")]
+    InvalidResolutionSource,
+    #[msg("Invalid oracle config")]
+    InvalidOracleConfig,
+    #[msg("Invalid oracle account")]
+    InvalidOracleAccount,
+    #[msg("Oracle price not fully verified")]
+    OracleNotVerified,
+    #[msg("Oracle price published before end time")]
+    OraclePriceTooEarly,
+    #[msg("Oracle price stale")]
+    OraclePriceStale,
+    #[msg("Invalid oracle price")]
+    InvalidOraclePrice,
+    #[msg("Oracle confidence too wide")]
+    OracleConfidenceTooWide,
+    #[msg("Invalid resolver set")]
+    InvalidResolverSet,
+    #[msg("Already voted")]
+    AlreadyVoted,
+    #[msg("Market trades outcome tokens")]
+    OutcomeTokenMarket,
+    #[msg("Market does not trade outcome tokens")]
+    NotOutcomeTokenMarket,
+    #[msg("Market already has bets")]
+    MarketHasBets,
+    #[msg("Invalid outcome token accounts")]
+    InvalidOutcomeAccounts,
+    #[msg("Not enough complete sets")]
+    InsufficientCompleteSets,
+    #[msg("Invalid new owner")]
+    InvalidNewOwner,
+    #[msg("Position asset accounts missing")]
+    MissingPositionAsset,
+    #[msg("Invalid position asset")]
+    InvalidPositionAsset,
+    #[msg("Position is held as an asset")]
+    PositionHasAsset,
+    #[msg("Liquidity locked until resolution")]
+    LiquidityLocked,
+    #[msg("Invalid seed liquidity")]
+    InvalidSeedLiquidity,
+    #[msg("Invalid LMSR liquidity parameter")]
+    InvalidLiquidityParameter,
+    #[msg("Not supported by this market's pricing mode")]
+    UnsupportedPricingMode,
+    #[msg("Invalid order")]
+    InvalidOrder,
+    #[msg("Order expired")]
+    OrderExpired,
+    #[msg("Order limit price not reached")]
+    OrderNotFillable,
+    #[msg("Position has shares locked in open orders")]
+    PositionLocked,
+    #[msg("Order book side is full")]
+    OrderBookFull,
+    #[msg("Order book event queue is full, crank it first")]
+    EventQueueFull,
+    #[msg("Order book fills must be settled first")]
+    UnsettledFills,
+    #[msg("No order book fills could be settled")]
+    NoFillsSettled,
+    #[msg("Invalid offer")]
+    InvalidOffer,
+    #[msg("Offer already accepted")]
+    OfferAlreadyAccepted,
+    #[msg("Offer not accepted")]
+    OfferNotAccepted,
+    #[msg("Offer expired")]
+    OfferExpired,
+    #[msg("Invalid parlay")]
+    InvalidParlay,
+    #[msg("Parlay vault cannot cover the payout")]
+    InsufficientUnderwriting,
+    #[msg("Parent market condition not met")]
+    ConditionNotMet,
+    #[msg("Conditional markets cannot use this feature")]
+    ConditionalMarket,
+    #[msg("Parent market resolved to the condition")]
+    ConditionMet,
+    #[msg("Position has open orders")]
+    PositionHasOpenOrders,
+    #[msg("Bond accounts missing")]
+    MissingBondAccounts,
+    #[msg("Leg price moved too far from its snapshot")]
+    LegPriceMoved,
+    #[msg("Position account missing")]
+    MissingPosition,
+    #[msg("Outcome token accounts missing")]
+    MissingOutcomeAccounts,
+}
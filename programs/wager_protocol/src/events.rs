@@ -1,6 +1,7 @@
 use anchor_lang::event;
 use anchor_lang::prelude::*;
 use anchor_lang::Discriminator;
+use crate::structs::{BookSide, OrderSide};
 
 
 #[event]
@@ -9,18 +10,131 @@ pub struct ProtocolInitialized {
     pub protocol_fee_bps: u16,
     pub cancel_fee_bps: u16,
     pub dev_recipient: Pubkey,
+    pub dev_fee_share_bps: u16,
 }
 #[event]
-pub struct MarketCreated { pub market: Pubkey, pub creator: Pubkey, pub id: u64, pub question: String, pub end_time: i64 }
+pub struct ProtocolConfigUpdated {
+    pub authority: Pubkey,
+    pub old_protocol_fee_bps: u16,
+    pub new_protocol_fee_bps: u16,
+    pub old_cancel_fee_bps: u16,
+    pub new_cancel_fee_bps: u16,
+    pub old_amm_fee: u16,
+    pub new_amm_fee: u16,
+}
+#[event]
+pub struct AuthorityProposed { pub authority: Pubkey, pub pending_authority: Pubkey }
+#[event]
+pub struct AuthorityTransferred { pub old_authority: Pubkey, pub new_authority: Pubkey }
+#[event]
+pub struct FeeRecipientUpdated { pub authority: Pubkey, pub old_recipient: Pubkey, pub new_recipient: Pubkey }
+#[event]
+pub struct FeeSplitUpdated {
+    pub authority: Pubkey,
+    pub old_dev_recipient: Pubkey,
+    pub new_dev_recipient: Pubkey,
+    pub old_dev_fee_share_bps: u16,
+    pub new_dev_fee_share_bps: u16,
+}
+#[event]
+pub struct ResolutionConfigUpdated { pub authority: Pubkey, pub dispute_window: i64, pub dispute_bond: u64, pub arbiter: Pubkey }
+#[event]
+pub struct ProtocolPauseUpdated { pub authority: Pubkey, pub paused: bool }
+#[event]
+pub struct MarketPauseUpdated { pub market: Pubkey, pub paused: bool }
+#[event]
+pub struct EmergencyModeEnabled { pub market: Pubkey, pub escrow_balance: u64, pub total_volume: u64 }
+#[event]
+pub struct EmergencyWithdrawn { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub amount: u64, pub payout: u64 }
+#[event]
+pub struct MarketCreated { pub market: Pubkey, pub creator: Pubkey, pub id: u64, pub question: String, pub end_time: i64, pub resolution_deadline: i64 }
 #[event]
-pub struct BetPlaced { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub outcome: u8, pub amount: u64 }
+pub struct BetPlaced { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub outcome: u8, pub amount: u64, pub shares: u64 }
 #[event]
-pub struct PositionIncreased { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub added_amount: u64 }
+pub struct PositionIncreased { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub added_amount: u64, pub shares: u64 }
 #[event]
 pub struct Withdrawn { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub withdrawn: u64, pub payout: u64, pub fee: u64 }
 #[event]
 pub struct PositionCancelled { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub amount: u64, pub payout: u64, pub fee: u64 }
 #[event]
+pub struct ResolutionProposed { pub market: Pubkey, pub proposer: Pubkey, pub outcome: Option<u8>, pub value: Option<i64>, pub bond: u64, pub dispute_deadline: i64 }
+#[event]
+pub struct ResolutionDisputed { pub market: Pubkey, pub disputer: Pubkey, pub bond: u64 }
+#[event]
+pub struct DisputeSettled { pub market: Pubkey, pub arbiter: Pubkey, pub proposer_won: bool, pub winner: Pubkey, pub bonds: u64 }
+#[event]
+pub struct OracleResolved { pub market: Pubkey, pub price: i128, pub exponent: i32, pub publish_time: i64, pub winner: u8 }
+#[event]
+pub struct ResolutionVoteSubmitted { pub market: Pubkey, pub resolver: Pubkey, pub outcome: u8, pub votes: u8, pub threshold: u8 }
+#[event]
 pub struct MarketResolved { pub market: Pubkey, pub winner: u8 }
 #[event]
-pub struct WinningsClaimed { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub winnings: u64 }
\ No newline at end of file
+pub struct ScalarMarketResolved { pub market: Pubkey, pub value: i64 }
+#[event]
+pub struct MarketVoided { pub market: Pubkey, pub expired: bool }
+#[event]
+pub struct RefundClaimed { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub amount: u64, pub refund: u64 }
+#[event]
+pub struct WinningsClaimed { pub market: Pubkey, pub position: Pubkey, pub user: Pubkey, pub winnings: u64 }
+#[event]
+pub struct OutcomeMintCreated { pub market: Pubkey, pub outcome: u8, pub mint: Pubkey }
+#[event]
+pub struct OutcomeTokensBought { pub market: Pubkey, pub user: Pubkey, pub outcome: u8, pub amount: u64, pub shares: u64 }
+#[event]
+pub struct OutcomeTokensSold { pub market: Pubkey, pub user: Pubkey, pub outcome: u8, pub amount: u64, pub payout: u64, pub fee: u64 }
+#[event]
+pub struct OutcomeTokensRedeemed { pub market: Pubkey, pub user: Pubkey, pub outcome: u8, pub amount: u64, pub payout: u64, pub fee: u64 }
+#[event]
+pub struct CollateralSplit { pub market: Pubkey, pub user: Pubkey, pub amount: u64 }
+#[event]
+pub struct PositionsMerged { pub market: Pubkey, pub user: Pubkey, pub amount: u64, pub payout: u64 }
+#[event]
+pub struct PositionTransferred { pub market: Pubkey, pub old_position: Pubkey, pub new_position: Pubkey, pub from: Pubkey, pub to: Pubkey, pub amount: u64 }
+#[event]
+pub struct PositionAssetMinted { pub market: Pubkey, pub position: Pubkey, pub asset: Pubkey, pub owner: Pubkey }
+#[event]
+pub struct LiquidityAdded { pub market: Pubkey, pub provider: Pubkey, pub amount: u64, pub shares: u64 }
+#[event]
+pub struct LiquidityRemoved { pub market: Pubkey, pub provider: Pubkey, pub shares: u64, pub payout: u64, pub fees: u64 }
+#[event]
+pub struct MarketSeeded { pub market: Pubkey, pub creator: Pubkey, pub amount: u64, pub odds_bps: Vec<u16> }
+#[event]
+pub struct SeedLiquidityClaimed { pub market: Pubkey, pub creator: Pubkey, pub stake: u64, pub payout: u64 }
+#[event]
+pub struct MarketSubsidized { pub market: Pubkey, pub creator: Pubkey, pub b: u64, pub subsidy: u64 }
+#[event]
+pub struct OrderPlaced { pub market: Pubkey, pub order: Pubkey, pub owner: Pubkey, pub side: OrderSide, pub outcome: u8, pub limit_price_bps: u16, pub amount: u64, pub expiry: i64 }
+#[event]
+pub struct OrderFilled { pub market: Pubkey, pub order: Pubkey, pub counter_order: Option<Pubkey>, pub shares: u64, pub collateral: u64 }
+#[event]
+pub struct OrderCancelled { pub market: Pubkey, pub order: Pubkey, pub owner: Pubkey, pub refund: u64, pub shares: u64 }
+#[event]
+pub struct OrderBookCreated { pub market: Pubkey, pub order_book: Pubkey }
+#[event]
+pub struct BookOrderPlaced { pub market: Pubkey, pub order_id: u64, pub owner: Pubkey, pub position: Pubkey, pub side: BookSide, pub price_bps: u16, pub quantity: u64, pub filled: u64, pub cost: u64 }
+#[event]
+pub struct BookOrderMatched { pub market: Pubkey, pub maker_order: u64, pub taker_order: u64, pub price_bps: u16, pub quantity: u64 }
+#[event]
+pub struct BookOrderCancelled { pub market: Pubkey, pub order_id: u64, pub owner: Pubkey, pub quantity: u64, pub refund: u64 }
+#[event]
+pub struct BookFillsSettled { pub market: Pubkey, pub fills: u64, pub shares: u64, pub pending_fills: u64 }
+#[event]
+pub struct OfferMade { pub market: Pubkey, pub offer: Pubkey, pub maker: Pubkey, pub taker: Option<Pubkey>, pub outcome: u8, pub maker_stake: u64, pub taker_stake: u64, pub expiry: i64 }
+#[event]
+pub struct OfferAccepted { pub market: Pubkey, pub offer: Pubkey, pub taker: Pubkey, pub taker_stake: u64 }
+#[event]
+pub struct OfferCancelled { pub market: Pubkey, pub offer: Pubkey, pub maker: Pubkey, pub refund: u64 }
+#[event]
+pub struct OfferSettled { pub market: Pubkey, pub offer: Pubkey, pub winner: Option<Pubkey>, pub maker_payout: u64, pub taker_payout: u64 }
+#[event]
+pub struct ParlayVaultFunded { pub vault: Pubkey, pub funder: Pubkey, pub amount: u64 }
+#[event]
+pub struct ParlayVaultWithdrawn { pub vault: Pubkey, pub amount: u64 }
+#[event]
+pub struct ParlayCreated { pub vault: Pubkey, pub parlay: Pubkey, pub user: Pubkey, pub stake: u64, pub max_payout: u64, pub legs: u8 }
+#[event]
+pub struct ParlaySettled { pub vault: Pubkey, pub parlay: Pubkey, pub user: Pubkey, pub payout: u64, pub void_legs: u8 }
+#[event]
+pub struct ParentMarketLinked { pub market: Pubkey, pub parent_market: Pubkey, pub parent_outcome: u8 }
+#[event]
+pub struct ConditionalMarketVoided { pub market: Pubkey, pub parent_market: Pubkey, pub parent_outcome: Option<u8> }
@@ -2,12 +2,18 @@ mod error;
 mod constants;
 mod structs;
 mod events;
+mod oracle;
+mod asset;
+mod lmsr;
 
 use structs::*;
 use events::*;
+use constants::*;
 use error::ErrorCode;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, TokenAccount, Token};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, Mint, TokenAccount, Token};
 
 //Program ID to change !!
 declare_id!("4HXdLHreKqwTNRDP4sVuUCzBEc6p89sXHp4auzzShbiB");
@@ -16,7 +22,6 @@ pub const PRECISION: u128 = 1_000_000_000_u128; // For fixed-point math
 
 #[program]
 pub mod wager_protocol {
-    use std::str::FromStr;
     use super::*;
 
     pub fn initialize_protocol(
@@ -25,7 +30,11 @@ pub mod wager_protocol {
         cancel_fee_bps: u16,
         amm_fee: u16,
         authority_fee_recipient: Pubkey, // deployer's fee wallet
+        dev_recipient: Pubkey,
+        dev_fee_share_bps: u16,          // share of every fee sent to dev_recipient
     ) -> Result<()> {
+        validate_fees(protocol_fee_bps, cancel_fee_bps, amm_fee)?;
+        require!(dev_fee_share_bps <= BPS_DENOMINATOR, ErrorCode::InvalidFeeShare);
 
         let protocol = &mut ctx.accounts.protocol;
         protocol.authority = ctx.accounts.authority.key();
@@ -34,26 +43,348 @@ pub mod wager_protocol {
         protocol.cancel_fee_bps = cancel_fee_bps;
         protocol.amm_fee = amm_fee;
         protocol.market_count = 0;
-        protocol.dev_recipient = Pubkey::from_str("8Nq7eMbvhZiPzZFeYutAoiHqF2uJTZZWwnBRzvkiUUid").unwrap();
+        protocol.pending_authority = None;
+        protocol.paused = false;
+        protocol.dev_recipient = dev_recipient;
+        protocol.dev_fee_share_bps = dev_fee_share_bps;
+        protocol.dispute_window = DEFAULT_DISPUTE_WINDOW;
+        protocol.dispute_bond = 0;
+        protocol.arbiter = Pubkey::default();
 
         emit!(ProtocolInitialized {
         authority: protocol.authority,
         protocol_fee_bps,
         cancel_fee_bps,
         dev_recipient: protocol.dev_recipient,
+        dev_fee_share_bps,
     });
         Ok(())
     }
 
-    /// Create a market. It requires exactly 2 outcomes.
+    /// Update the protocol fees (authority only). `None` leaves a fee unchanged.
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        protocol_fee_bps: Option<u16>,
+        cancel_fee_bps: Option<u16>,
+        amm_fee: Option<u16>,
+    ) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+
+        let old_protocol_fee_bps = protocol.protocol_fee_bps;
+        let old_cancel_fee_bps = protocol.cancel_fee_bps;
+        let old_amm_fee = protocol.amm_fee;
+
+        let new_protocol_fee_bps = protocol_fee_bps.unwrap_or(old_protocol_fee_bps);
+        let new_cancel_fee_bps = cancel_fee_bps.unwrap_or(old_cancel_fee_bps);
+        let new_amm_fee = amm_fee.unwrap_or(old_amm_fee);
+
+        validate_fees(new_protocol_fee_bps, new_cancel_fee_bps, new_amm_fee)?;
+
+        protocol.protocol_fee_bps = new_protocol_fee_bps;
+        protocol.cancel_fee_bps = new_cancel_fee_bps;
+        protocol.amm_fee = new_amm_fee;
+
+        emit!(ProtocolConfigUpdated {
+            authority: protocol.authority,
+            old_protocol_fee_bps,
+            new_protocol_fee_bps,
+            old_cancel_fee_bps,
+            new_cancel_fee_bps,
+            old_amm_fee,
+            new_amm_fee,
+        });
+        Ok(())
+    }
+
+    /// Propose a new protocol authority. It only takes over once it calls `accept_authority`.
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+        protocol.pending_authority = Some(new_authority);
+
+        emit!(AuthorityProposed {
+            authority: protocol.authority,
+            pending_authority: new_authority
+        });
+        Ok(())
+    }
+
+    /// Accept a pending authority transfer (signed by the proposed key).
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+        let pending = protocol.pending_authority.ok_or(ErrorCode::NoPendingAuthority)?;
+        require_keys_eq!(ctx.accounts.new_authority.key(), pending, ErrorCode::Unauthorized);
+
+        let old_authority = protocol.authority;
+        protocol.authority = pending;
+        protocol.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            old_authority,
+            new_authority: pending
+        });
+        Ok(())
+    }
+
+    /// Rotate the wallet receiving the authority share of the fees.
+    pub fn update_fee_recipient(
+        ctx: Context<UpdateFeeRecipient>,
+        new_recipient: Pubkey,
+    ) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+        let old_recipient = protocol.authority_fee_recipient;
+        protocol.authority_fee_recipient = new_recipient;
+
+        emit!(FeeRecipientUpdated {
+            authority: protocol.authority,
+            old_recipient,
+            new_recipient
+        });
+        Ok(())
+    }
+
+    /// Change the dev recipient and/or the dev share of the fees (authority only).
+    pub fn update_fee_split(
+        ctx: Context<UpdateFeeSplit>,
+        dev_recipient: Option<Pubkey>,
+        dev_fee_share_bps: Option<u16>,
+    ) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+
+        let old_dev_recipient = protocol.dev_recipient;
+        let old_dev_fee_share_bps = protocol.dev_fee_share_bps;
+
+        let new_dev_recipient = dev_recipient.unwrap_or(old_dev_recipient);
+        let new_dev_fee_share_bps = dev_fee_share_bps.unwrap_or(old_dev_fee_share_bps);
+        require!(new_dev_fee_share_bps <= BPS_DENOMINATOR, ErrorCode::InvalidFeeShare);
+
+        protocol.dev_recipient = new_dev_recipient;
+        protocol.dev_fee_share_bps = new_dev_fee_share_bps;
+
+        emit!(FeeSplitUpdated {
+            authority: protocol.authority,
+            old_dev_recipient,
+            new_dev_recipient,
+            old_dev_fee_share_bps,
+            new_dev_fee_share_bps,
+        });
+        Ok(())
+    }
+
+    /// Configure the optimistic resolution: challenge window, bond and optional arbiter (authority only).
+    /// The protocol authority can always settle disputes; `arbiter` is an additional key that can too.
+    pub fn update_resolution_config(
+        ctx: Context<UpdateResolutionConfig>,
+        dispute_window: Option<i64>,
+        dispute_bond: Option<u64>,
+        arbiter: Option<Pubkey>,
+    ) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+
+        if let Some(dispute_window) = dispute_window {
+            require!(dispute_window > 0 && dispute_window <= MAX_DISPUTE_WINDOW, ErrorCode::InvalidDisputeWindow);
+            protocol.dispute_window = dispute_window;
+        }
+        if let Some(dispute_bond) = dispute_bond {
+            protocol.dispute_bond = dispute_bond;
+        }
+        if let Some(arbiter) = arbiter {
+            protocol.arbiter = arbiter;
+        }
+
+        emit!(ResolutionConfigUpdated {
+            authority: protocol.authority,
+            dispute_window: protocol.dispute_window,
+            dispute_bond: protocol.dispute_bond,
+            arbiter: protocol.arbiter,
+        });
+        Ok(())
+    }
+
+    /// Pause or unpause trading on every market.
+    pub fn set_protocol_paused(ctx: Context<SetProtocolPaused>, paused: bool) -> Result<()> {
+        let protocol = &mut ctx.accounts.protocol;
+        protocol.paused = paused;
+
+        emit!(ProtocolPauseUpdated {
+            authority: protocol.authority,
+            paused
+        });
+        Ok(())
+    }
+
+    /// Pause or unpause trading on a single market.
+    pub fn set_market_paused(ctx: Context<SetMarketPaused>, paused: bool) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        market.paused = paused;
+
+        emit!(MarketPauseUpdated {
+            market: market.key(),
+            paused
+        });
+        Ok(())
+    }
+
+    /// Put an unresolved market in emergency mode. This is irreversible: trading and resolution
+    /// are disabled and positions can only be refunded through `emergency_withdraw`.
+    pub fn enable_emergency_mode(ctx: Context<EnableEmergencyMode>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(market.proposal.is_none(), ErrorCode::ResolutionPending);
+        require!(market.pending_fills == 0, ErrorCode::UnsettledFills);
+
+        market.paused = true;
+        market.emergency = true;
+
+        emit!(EmergencyModeEnabled {
+            market: market.key(),
+            escrow_balance: ctx.accounts.market_escrow.amount,
+            total_volume: market.total_volume
+        });
+        Ok(())
+    }
+
+    /// Reclaim the collateral a position paid for its shares from a market in emergency mode, pro
+    /// rata from the escrow like `claim_refund` if the escrow falls short. Outcome tokens are burned
+    /// and refunded at their outcome's average cost.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.emergency, ErrorCode::NotEmergencyMode);
+
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        let (idx, amount, cost) = match tokens {
+            Some((_, user_outcome_account, outcome)) => {
+                let amount = user_outcome_account.amount;
+                (outcome, amount, market.average_cost(outcome, amount)?)
+            }
+            None => {
+                let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+                require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+                require!(!position.claimed, ErrorCode::AlreadyClaimed);
+                require!(position.locked == 0, ErrorCode::PositionLocked);
+                (position.outcome as usize, position.amount, position.cost)
+            }
+        };
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let payout = market.refund_value(cost, ctx.accounts.market_escrow.amount)?;
+        let cost = match tokens {
+            Some((outcome_mint, user_outcome_account, _)) => {
+                burn_outcome_tokens(outcome_mint, user_outcome_account, &ctx.accounts.user, &ctx.accounts.token_program, amount)?;
+                cost
+            }
+            None => {
+                let position = ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?;
+                position.claimed = true;
+                position.release(amount)?
+            }
+        };
+        market.release_shares(idx, amount, cost)?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            payout
+        )?;
+
+        if tokens.is_some() {
+            emit!(OutcomeTokensRedeemed {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome: idx as u8,
+                amount,
+                payout,
+                fee: 0
+            });
+            return Ok(());
+        }
+
+        let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+        burn_position_asset(position, &ctx.accounts.position_asset, &ctx.accounts.mpl_core_program, &ctx.accounts.user)?;
+
+        emit!(EmergencyWithdrawn {
+            market: market.key(),
+            position: position.key(),
+            user: position.user,
+            amount,
+            payout
+        });
+        Ok(())
+    }
+
+    /// Create a market. Categorical markets take 2..=16 outcomes, scalar markets exactly 2 (LONG, SHORT).
+    /// The creator can seed the pools with liquidity at chosen starting odds, or price the market
+    /// with LMSR and fund its `b * ln(N)` subsidy.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_market(
         ctx: Context<CreateMarket>,
         question: String,
         outcomes: Vec<String>,
         end_time: i64,
+        resolution_deadline: i64,
+        market_type: MarketType,
+        resolution_source: ResolutionSource,
+        seed_liquidity: Option<SeedLiquidity>,
+        pricing: PricingMode,
     ) -> Result<()> {
-        require!(outcomes.len() == 2, ErrorCode::InvalidOutcomes);
+        require!((MIN_OUTCOMES..=MAX_OUTCOMES).contains(&outcomes.len()), ErrorCode::InvalidOutcomes);
+        let subsidy = match pricing {
+            PricingMode::ConstantProduct => 0,
+            PricingMode::Lmsr { b } => {
+                require!(seed_liquidity.is_none(), ErrorCode::UnsupportedPricingMode);
+                lmsr::max_loss(b, outcomes.len())?
+            }
+            PricingMode::OrderBook => {
+                require!(outcomes.len() == 2 && seed_liquidity.is_none(), ErrorCode::UnsupportedPricingMode);
+                0
+            }
+        };
+        if let MarketType::Scalar { lower_bound, upper_bound } = market_type {
+            require!(outcomes.len() == 2, ErrorCode::InvalidOutcomes);
+            require!(lower_bound < upper_bound, ErrorCode::InvalidScalarRange);
+        }
+        match resolution_source {
+            ResolutionSource::Creator => {}
+            ResolutionSource::Pyth { max_staleness, max_confidence_bps, .. } => {
+                require!(market_type == MarketType::Categorical && outcomes.len() == 2, ErrorCode::InvalidOutcomes);
+                require!(max_staleness > 0 && max_confidence_bps <= BPS_DENOMINATOR, ErrorCode::InvalidOracleConfig);
+            }
+            ResolutionSource::Switchboard { max_staleness_slots, .. } => {
+                require!(market_type == MarketType::Categorical && outcomes.len() == 2, ErrorCode::InvalidOutcomes);
+                require!(max_staleness_slots > 0, ErrorCode::InvalidOracleConfig);
+            }
+            ResolutionSource::Committee { resolvers, resolver_count, threshold } => {
+                require!(market_type == MarketType::Categorical, ErrorCode::InvalidMarketType);
+                let count = resolver_count as usize;
+                require!(count > 0 && count <= MAX_RESOLVERS, ErrorCode::InvalidResolverSet);
+                require!(threshold > 0 && threshold <= resolver_count, ErrorCode::InvalidResolverSet);
+
+                let set = &resolvers[..count];
+                require!(set.iter().all(|r| *r != Pubkey::default()), ErrorCode::InvalidResolverSet);
+                require!(
+                    set.iter().enumerate().all(|(i, r)| !set[..i].contains(r)),
+                    ErrorCode::InvalidResolverSet
+                );
+            }
+        }
+        require!(question.len() <= MAX_QUESTION_LEN, ErrorCode::QuestionTooLong);
+        require!(outcomes.iter().all(|o| o.len() <= MAX_OUTCOME_LEN), ErrorCode::InvalidOutcomes);
         require!(end_time > Clock::get()?.unix_timestamp, ErrorCode::InvalidEndTime);
+        require!(resolution_deadline > end_time, ErrorCode::InvalidResolutionDeadline);
         require_eq!(ctx.accounts.market_escrow.amount, 0, ErrorCode::EscrowNotEmpty);
 
         let market = &mut ctx.accounts.market;
@@ -63,11 +394,86 @@ pub mod wager_protocol {
         market.question = question;
         market.outcomes = outcomes;
         market.end_time = end_time;
+        market.resolution_deadline = resolution_deadline;
         market.resolved = false;
         market.total_volume = 0;
-        market.outcome_pools = vec![0u64, 0u64];
+        market.outcome_pools = vec![0u64; market.outcomes.len()];
         market.winning_outcome = None;
+        market.market_type = market_type;
+        market.resolved_value = None;
+        market.voided = false;
+        market.token_mint = ctx.accounts.token_mint.key();
+        market.proposal = None;
+        market.resolution_source = resolution_source;
         market.position_count = 0;
+        market.paused = false;
+        market.emergency = false;
+        market.outcome_mint_count = 0;
+        market.lp_reserve = vec![0u64; market.outcomes.len()];
+        market.lp_fees = 0;
+        // The LMSR subsidy is the creator's liquidity, owed what is left once the shares are paid
+        market.seed_shares = subsidy;
+        market.outcome_shares = vec![0u64; market.outcomes.len()];
+        market.pricing = pricing;
+        market.subsidy = subsidy;
+        market.order_count = 0;
+        market.pending_fills = 0;
+        market.offer_count = 0;
+        market.parent = None;
+        market.lp_shares = subsidy;
+        market.outcome_costs = vec![0u64; market.outcomes.len()];
+        market.price_snapshot = vec![0u64; market.outcomes.len()];
+        market.snapshot_time = 0;
+        market.parlay_legs = 0;
+
+        if let Some(seed) = &seed_liquidity {
+            let amount = market.seed_pools(seed)?;
+            let creator_token_account = ctx.accounts.creator_token_account.as_ref()
+                .ok_or(ErrorCode::InvalidSeedLiquidity)?;
+
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: creator_token_account.to_account_info(),
+                        to: ctx.accounts.market_escrow.to_account_info(),
+                        authority: ctx.accounts.creator.to_account_info(),
+                    },
+                ),
+                amount
+            )?;
+
+            emit!(MarketSeeded {
+                market: market.key(),
+                creator: market.creator,
+                amount,
+                odds_bps: seed.odds_bps.clone()
+            });
+        }
+
+        if let PricingMode::Lmsr { b } = pricing {
+            let creator_token_account = ctx.accounts.creator_token_account.as_ref()
+                .ok_or(ErrorCode::InvalidLiquidityParameter)?;
+
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: creator_token_account.to_account_info(),
+                        to: ctx.accounts.market_escrow.to_account_info(),
+                        authority: ctx.accounts.creator.to_account_info(),
+                    },
+                ),
+                subsidy
+            )?;
+
+            emit!(MarketSubsidized {
+                market: market.key(),
+                creator: market.creator,
+                b,
+                subsidy
+            });
+        }
 
         ctx.accounts.protocol.market_count = ctx.accounts.protocol.market_count
             .checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
@@ -77,24 +483,36 @@ pub mod wager_protocol {
             creator: market.creator,
             id: market.id,
             question: market.question.clone(),
-            end_time
+            end_time,
+            resolution_deadline
         });
         Ok(())
     }
 
-    /// Place a new bet -> creates a new Position PDA (history preserved).
+    /// Place a new bet -> creates a new Position PDA (history preserved), or in an outcome token
+    /// market mints the shares to `user_outcome_account` instead.
+    /// `amount` of collateral buys shares on the AMM curve, at least `min_shares_out` of them.
     pub fn place_bet(
         ctx: Context<PlaceBet>,
         outcome: u8,
         amount: u64,
+        min_shares_out: u64,
     ) -> Result<()> {
-        require!(outcome < 2, ErrorCode::InvalidOutcome);
         require!(amount > 0, ErrorCode::InvalidAmount);
 
         let market = &mut ctx.accounts.market;
+        require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
         require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
         require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketEnded);
 
+        let quote = market.buy_quote(outcome as usize, amount, ctx.accounts.protocol.amm_fee)?;
+        let shares = quote.shares;
+        require!(shares > 0, ErrorCode::InvalidAmount);
+        // Slippage protection
+        require!(shares >= min_shares_out, ErrorCode::SlippageExceeded);
+
         // Transfer tokens
         let cpi_accounts = token::Transfer {
             from: ctx.accounts.user_token_account.to_account_info(),
@@ -103,46 +521,115 @@ pub mod wager_protocol {
         };
         token::transfer(CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts), amount)?;
 
-        // Add to pool
-        market.outcome_pools[outcome as usize] = market.outcome_pools[outcome as usize]
-            .checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
-        market.total_volume = market.total_volume.checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+        market.record_prices(Clock::get()?.unix_timestamp)?;
+        market.apply_buy(outcome as usize, amount, &quote)?;
+
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        if let Some((outcome_mint, user_outcome_account, held)) = tokens {
+            require!(held == outcome as usize, ErrorCode::InvalidOutcomeAccounts);
+            require!(ctx.accounts.position.is_none(), ErrorCode::OutcomeTokenMarket);
+
+            let id_bytes = market.id.to_le_bytes();
+            let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::MintTo {
+                        mint: outcome_mint.to_account_info(),
+                        to: user_outcome_account.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                shares
+            )?;
+
+            emit!(OutcomeTokensBought {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome,
+                amount,
+                shares
+            });
+            return Ok(());
+        }
 
         // Create position tracking
-        let position = &mut ctx.accounts.position;
+        let position = ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?;
         position.id = market.position_count;
-        position.bump = ctx.bumps.position;
+        position.bump = ctx.bumps.position.ok_or(ErrorCode::MissingPosition)?;
         position.user = ctx.accounts.user.key();
         position.market = market.key();
         position.outcome = outcome;
-        position.amount = amount;
+        position.amount = shares;
         position.claimed = false;
         position.ts = Clock::get()?.unix_timestamp;
+        position.asset = None;
+        position.locked = 0;
+        position.cost = amount;
+        position.open_orders = 0;
         market.position_count = market.position_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
 
+        // Optionally mirror the position as a Core asset; its holder then controls the position
+        if let Some(position_asset) = &ctx.accounts.position_asset {
+            let mpl_core_program = ctx.accounts.mpl_core_program.as_ref().ok_or(ErrorCode::MissingPositionAsset)?;
+            asset::create_position_asset(
+                mpl_core_program,
+                position_asset,
+                &ctx.accounts.user,
+                &ctx.accounts.user,
+                &market.to_account_info(),
+                &ctx.accounts.system_program,
+                &format!("Wager #{}-{}", market.id, position.id),
+                &asset::position_attributes(&market.key(), &market.outcomes[outcome as usize], shares)
+            )?;
+            position.asset = Some(position_asset.key());
+
+            emit!(PositionAssetMinted {
+                market: market.key(),
+                position: position.key(),
+                asset: position_asset.key(),
+                owner: position.user
+            });
+        }
+
         emit!(BetPlaced {
             market: market.key(),
             position: position.key(),
             user: position.user,
             outcome,
-            amount
+            amount,
+            shares
         });
 
         Ok(())
     }
 
     /// Increase funds in an existing position (convenience). Snapshots the last added chunk and updates ts.
+    /// Priced like `place_bet`, with the same `min_shares_out` slippage check.
     pub fn increase_position(
         ctx: Context<IncreasePosition>,
         added_amount: u64,
+        min_shares_out: u64,
     ) -> Result<()> {
         require!(added_amount > 0, ErrorCode::InvalidAmount);
         let market = &mut ctx.accounts.market;
+        require!(market.outcome_mint_count == 0, ErrorCode::OutcomeTokenMarket);
         require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
         require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketEnded);
 
         let position = &mut ctx.accounts.position;
-        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
+        require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+
+        let outcome_index = position.outcome as usize;
+        let quote = market.buy_quote(outcome_index, added_amount, ctx.accounts.protocol.amm_fee)?;
+        let shares = quote.shares;
+        require!(shares > 0, ErrorCode::InvalidAmount);
+        // Slippage protection
+        require!(shares >= min_shares_out, ErrorCode::SlippageExceeded);
 
         // Transfer tokens
         let cpi_accounts = token::Transfer {
@@ -153,25 +640,38 @@ pub mod wager_protocol {
         token::transfer(CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts), added_amount)?;
 
         // Update pools
-        let outcome_index = position.outcome as usize;
-        market.outcome_pools[outcome_index] = market.outcome_pools[outcome_index]
-            .checked_add(added_amount).ok_or(ErrorCode::AmountOverflow)?;
-        market.total_volume = market.total_volume.checked_add(added_amount).ok_or(ErrorCode::AmountOverflow)?;
+        market.record_prices(Clock::get()?.unix_timestamp)?;
+        market.apply_buy(outcome_index, added_amount, &quote)?;
 
         // Update position
-        position.amount = position.amount.checked_add(added_amount).ok_or(ErrorCode::AmountOverflow)?;
+        position.amount = position.amount.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+        position.cost = position.cost.checked_add(added_amount).ok_or(ErrorCode::AmountOverflow)?;
         position.ts = Clock::get()?.unix_timestamp;
 
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+        sync_position_asset(
+            market,
+            position,
+            &ctx.accounts.position_asset,
+            &ctx.accounts.mpl_core_program,
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            signer_seeds
+        )?;
+
         emit!(PositionIncreased {
             market: market.key(),
             position: position.key(),
             user: position.user,
-            added_amount
+            added_amount,
+            shares
         });
         Ok(())
     }
 
-    /// Withdraw the partial amount from a Position before the market end (AMM-style sell).
+    /// Withdraw the partial amount from a Position before the market end (AMM-style sell). In an
+    /// outcome token market the shares are burned from `user_outcome_account` instead.
     pub fn withdraw_from_position(
         ctx: Context<WithdrawFromPosition>,
         amount_to_withdraw: u64,
@@ -181,7 +681,6 @@ pub mod wager_protocol {
 
         let market = &mut ctx.accounts.market;
         let protocol = &ctx.accounts.protocol;
-        let position = &mut ctx.accounts.position;
 
         require_keys_eq!(
             ctx.accounts.authority_fee_recipient.key(),
@@ -189,54 +688,43 @@ pub mod wager_protocol {
             ErrorCode::InvalidFeeRecipient
         );
 
+        require!(!protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
         require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketAlreadyEndedForModification);
-        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
-        require!(amount_to_withdraw <= position.amount, ErrorCode::WithdrawAmountExceedsPosition);
-
-        let idx = position.outcome as usize;
-        let pool_outcome = market.outcome_pools[idx] as u128;
-        let pool_other = market.outcome_pools[1 - idx] as u128;
-
-        require!(pool_outcome > amount_to_withdraw as u128, ErrorCode::InsufficientLiquidity);
-        require!(pool_other > 0, ErrorCode::InsufficientLiquidity);
-
-        // Constant product: k = x * y
-        let k = pool_outcome.checked_mul(pool_other).ok_or(ErrorCode::AmountOverflow)?;
-
-        // Remove tokens from the outcome pool
-        let new_pool_outcome = pool_outcome.checked_sub(amount_to_withdraw as u128)
-            .ok_or(ErrorCode::AmountOverflow)?;
-
-        // Calculate the new pool to maintain k
-        let new_pool_other = k.checked_div(new_pool_outcome).ok_or(ErrorCode::AmountOverflow)?;
-
-        let payout_gross = new_pool_other.checked_sub(pool_other).ok_or(ErrorCode::AmountOverflow)?;
-        require!(payout_gross <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let payout_gross_u64 = payout_gross as u64;
-
-        // Apply fees
-        let amm_fee = payout_gross
-            .checked_mul(ctx.accounts.protocol.amm_fee as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(10_000).ok_or(ErrorCode::AmountOverflow)?;
-
-        let cancel_fee = payout_gross
-            .checked_mul(protocol.cancel_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(10_000).ok_or(ErrorCode::AmountOverflow)?;
 
-        let total_fee = amm_fee.checked_add(cancel_fee).ok_or(ErrorCode::AmountOverflow)?;
-        require!(total_fee <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let total_fee_u64 = total_fee as u64;
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        let idx = match tokens {
+            Some((_, user_outcome_account, outcome)) => {
+                require!(amount_to_withdraw <= user_outcome_account.amount, ErrorCode::WithdrawAmountExceedsPosition);
+                outcome
+            }
+            None => {
+                let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+                require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+                // Shares locked by sell orders stay on the position until the orders fill or are cancelled
+                require!(amount_to_withdraw <= position.free()?, ErrorCode::WithdrawAmountExceedsPosition);
+                position.outcome as usize
+            }
+        };
 
-        let payout_net = payout_gross_u64.checked_sub(total_fee_u64).ok_or(ErrorCode::AmountOverflow)?;
+        let quote = market.sell_quote(idx, amount_to_withdraw, protocol.amm_fee, protocol.cancel_fee_bps)?;
+        let payout_net = quote.payout_net;
+        let total_fee_u64 = quote.fee;
 
         // Slippage protection
         require!(payout_net >= min_payout, ErrorCode::SlippageExceeded);
 
         // Update pools to maintain AMM invariant
-        position.amount = position.amount.checked_sub(amount_to_withdraw).ok_or(ErrorCode::AmountOverflow)?;
-        market.outcome_pools[idx] = new_pool_outcome.try_into().map_err(|_| ErrorCode::AmountOverflow)?;
-        market.outcome_pools[1 - idx] = new_pool_other.try_into().map_err(|_| ErrorCode::AmountOverflow)?;
-        market.total_volume = market.total_volume.checked_sub(amount_to_withdraw).ok_or(ErrorCode::AmountOverflow)?;
+        let cost = match tokens {
+            Some((outcome_mint, user_outcome_account, _)) => {
+                burn_outcome_tokens(outcome_mint, user_outcome_account, &ctx.accounts.user, &ctx.accounts.token_program, amount_to_withdraw)?;
+                market.average_cost(idx, amount_to_withdraw)?
+            }
+            None => ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?.release(amount_to_withdraw)?,
+        };
+        market.record_prices(Clock::get()?.unix_timestamp)?;
+        let protocol_fee = market.apply_sell(idx, amount_to_withdraw, cost, &quote)?;
 
         // Transfers
         let id_bytes = market.id.to_le_bytes();
@@ -257,22 +745,39 @@ pub mod wager_protocol {
             payout_net
         )?;
 
-        // Fees: need to add tokens back to the opposite pool
-        let tokens_to_add = payout_gross_u64.checked_sub(payout_net).ok_or(ErrorCode::AmountOverflow)?;
-        if tokens_to_add > 0 {
-            market.outcome_pools[1 - idx] = market.outcome_pools[1 - idx]
-                .checked_add(tokens_to_add).ok_or(ErrorCode::AmountOverflow)?;
-        }
-
         // Distribute protocol fees
         distribute_fees(
             &ctx.accounts.market_escrow,
-            &ctx.accounts.authority_fee_recipient,
-            &ctx.accounts.dev_token_account,
+            ctx.accounts.protocol_token_account.as_ref(),
+            ctx.accounts.dev_token_account.as_ref(),
             &market.to_account_info(),
             &ctx.accounts.token_program,
             signer_seeds,
-            total_fee_u64
+            protocol_fee,
+            protocol.dev_fee_share_bps
+        )?;
+
+        if tokens.is_some() {
+            emit!(OutcomeTokensSold {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome: idx as u8,
+                amount: amount_to_withdraw,
+                payout: payout_net,
+                fee: total_fee_u64
+            });
+            return Ok(());
+        }
+
+        let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+        sync_position_asset(
+            market,
+            position,
+            &ctx.accounts.position_asset,
+            &ctx.accounts.mpl_core_program,
+            &ctx.accounts.user,
+            &ctx.accounts.system_program,
+            signer_seeds
         )?;
 
         emit!(Withdrawn {
@@ -287,13 +792,13 @@ pub mod wager_protocol {
         Ok(())
     }
 
-    /// Cancel the entire position BEFORE market end (AMM-style full sell with cancel fee).
+    /// Cancel the entire position BEFORE market end (AMM-style full sell with cancel fee). In an
+    /// outcome token market every token in `user_outcome_account` is burned and sold instead.
     pub fn cancel_position(
         ctx: Context<CancelPosition>,
         min_payout: u64,
     ) -> Result<()> {
         let market = &mut ctx.accounts.market;
-        let position = &mut ctx.accounts.position;
         let protocol = &ctx.accounts.protocol;
 
         require_keys_eq!(
@@ -301,49 +806,39 @@ pub mod wager_protocol {
             protocol.authority_fee_recipient,
             ErrorCode::InvalidFeeRecipient
         );
+        require!(!protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
         require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketAlreadyEndedForModification);
-        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
-        require!(!position.claimed, ErrorCode::AlreadyClaimed);
-        require!(position.amount > 0, ErrorCode::InvalidAmount);
-
-        let amount_to_return = position.amount;
-        let idx = position.outcome as usize;
-        let pool_outcome = market.outcome_pools[idx] as u128;
-        let pool_other = market.outcome_pools[1 - idx] as u128;
-
-        require!(pool_outcome > amount_to_return as u128, ErrorCode::InsufficientLiquidity);
-        require!(pool_other > 0, ErrorCode::InsufficientLiquidity);
 
-        // Constant product AMM
-        let k = pool_outcome.checked_mul(pool_other).ok_or(ErrorCode::AmountOverflow)?;
-        let new_pool_outcome = pool_outcome.checked_sub(amount_to_return as u128)
-            .ok_or(ErrorCode::AmountOverflow)?;
-        let new_pool_other = k.checked_div(new_pool_outcome).ok_or(ErrorCode::AmountOverflow)?;
-        let payout_gross = new_pool_other.checked_sub(pool_other).ok_or(ErrorCode::AmountOverflow)?;
-
-        require!(payout_gross <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let payout_gross_u64 = payout_gross as u64;
-
-        // Fees
-        let amm_fee = payout_gross
-            .checked_mul(ctx.accounts.protocol.amm_fee as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(10_000u128).ok_or(ErrorCode::AmountOverflow)?;
-
-        let cancel_fee = payout_gross
-            .checked_mul(protocol.cancel_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(10_000u128).ok_or(ErrorCode::AmountOverflow)?;
-
-        let total_fee = amm_fee.checked_add(cancel_fee).ok_or(ErrorCode::AmountOverflow)?;
-        require!(total_fee <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let total_fee_u64 = total_fee as u64;
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        let (idx, amount_to_return) = match tokens {
+            Some((_, user_outcome_account, outcome)) => (outcome, user_outcome_account.amount),
+            None => {
+                let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+                require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+                require!(!position.claimed, ErrorCode::AlreadyClaimed);
+                require!(position.locked == 0, ErrorCode::PositionLocked);
+                (position.outcome as usize, position.amount)
+            }
+        };
+        require!(amount_to_return > 0, ErrorCode::InvalidAmount);
 
-        let payout_net = payout_gross_u64.checked_sub(total_fee_u64).ok_or(ErrorCode::AmountOverflow)?;
+        let quote = market.sell_quote(idx, amount_to_return, protocol.amm_fee, protocol.cancel_fee_bps)?;
+        let payout_net = quote.payout_net;
+        let total_fee_u64 = quote.fee;
         require!(payout_net >= min_payout, ErrorCode::SlippageExceeded);
 
         // Update pools
-        market.outcome_pools[idx] = new_pool_outcome.try_into().map_err(|_| ErrorCode::AmountOverflow)?;
-        market.outcome_pools[1 - idx] = new_pool_other.try_into().map_err(|_| ErrorCode::AmountOverflow)?;
-        market.total_volume = market.total_volume.checked_sub(amount_to_return).ok_or(ErrorCode::AmountOverflow)?;
+        let cost = match tokens {
+            Some((outcome_mint, user_outcome_account, _)) => {
+                burn_outcome_tokens(outcome_mint, user_outcome_account, &ctx.accounts.user, &ctx.accounts.token_program, amount_to_return)?;
+                market.average_cost(idx, amount_to_return)?
+            }
+            None => ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?.release(amount_to_return)?,
+        };
+        market.record_prices(Clock::get()?.unix_timestamp)?;
+        let protocol_fee = market.apply_sell(idx, amount_to_return, cost, &quote)?;
 
         // Transfers
         let id_bytes = market.id.to_le_bytes();
@@ -363,26 +858,35 @@ pub mod wager_protocol {
             payout_net
         )?;
 
-        // Add fees back to the opposite pool
-        let tokens_to_add = payout_gross_u64.checked_sub(payout_net).ok_or(ErrorCode::AmountOverflow)?;
-        if tokens_to_add > 0 {
-            market.outcome_pools[1 - idx] = market.outcome_pools[1 - idx]
-                .checked_add(tokens_to_add).ok_or(ErrorCode::AmountOverflow)?;
-        }
-
         distribute_fees(
             &ctx.accounts.market_escrow,
-            &ctx.accounts.authority_fee_recipient,
-            &ctx.accounts.dev_token_account,
+            ctx.accounts.protocol_token_account.as_ref(),
+            ctx.accounts.dev_token_account.as_ref(),
             &market.to_account_info(),
             &ctx.accounts.token_program,
             signer_seeds,
-            total_fee_u64
+            protocol_fee,
+            protocol.dev_fee_share_bps
         )?;
 
+        if tokens.is_some() {
+            emit!(OutcomeTokensSold {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome: idx as u8,
+                amount: amount_to_return,
+                payout: payout_net,
+                fee: total_fee_u64
+            });
+            return Ok(());
+        }
+
+        let position = ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?;
         position.amount = 0;
         position.claimed = true;
 
+        burn_position_asset(position, &ctx.accounts.position_asset, &ctx.accounts.mpl_core_program, &ctx.accounts.user)?;
+
         emit!(PositionCancelled {
             market: market.key(),
             position: position.key(),
@@ -396,152 +900,2422 @@ pub mod wager_protocol {
     }
 
 
-    /// Resolve market (unchanged behavior — but note: creator resolving is trustful)
+    /// Propose the winning outcome. It becomes final after the dispute window through
+    /// `finalize_resolution`, or after arbitration if someone calls `dispute_resolution`.
     pub fn resolve_market(
         ctx: Context<ResolveMarket>,
         winning_outcome: u8,
     ) -> Result<()> {
-        let market = &mut ctx.accounts.market;
+        let market = &ctx.accounts.market;
         require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(market.resolution_source == ResolutionSource::Creator, ErrorCode::InvalidResolutionSource);
         require!(!market.resolved, ErrorCode::AlreadyResolved);
-        require!(Clock::get()?.unix_timestamp >= market.end_time, ErrorCode::MarketNotEnded);
-        require!(winning_outcome < 2, ErrorCode::InvalidOutcome);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(market.proposal.is_none(), ErrorCode::ResolutionPending);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= market.end_time, ErrorCode::MarketNotEnded);
+        require!(now < market.resolution_deadline, ErrorCode::ResolutionDeadlinePassed);
+        require!((winning_outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(market.market_type == MarketType::Categorical, ErrorCode::InvalidMarketType);
+
+        propose_resolution(ctx.accounts, Some(winning_outcome), None)
+    }
 
-        market.resolved = true;
-        market.winning_outcome = Some(winning_outcome);
+    /// Propose the resolved value of a scalar market. Values outside the range are clamped at payout.
+    pub fn resolve_scalar_market(
+        ctx: Context<ResolveMarket>,
+        value: i64,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(market.resolution_source == ResolutionSource::Creator, ErrorCode::InvalidResolutionSource);
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(market.proposal.is_none(), ErrorCode::ResolutionPending);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= market.end_time, ErrorCode::MarketNotEnded);
+        require!(now < market.resolution_deadline, ErrorCode::ResolutionDeadlinePassed);
+        require!(matches!(market.market_type, MarketType::Scalar { .. }), ErrorCode::InvalidMarketType);
+
+        propose_resolution(ctx.accounts, None, Some(value))
+    }
 
-        emit!(MarketResolved {
-            market: market.key(),
-            winner: winning_outcome
+    /// Challenge a pending resolution by posting the same bond as the proposer.
+    /// The dispute is then settled by the protocol authority or arbiter with `arbitrate_dispute`.
+    pub fn dispute_resolution(ctx: Context<DisputeResolution>) -> Result<()> {
+        let market_key = ctx.accounts.market.key();
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+
+        let proposal = market.proposal.as_mut().ok_or(ErrorCode::NoResolutionProposed)?;
+        require!(proposal.disputer.is_none(), ErrorCode::AlreadyDisputed);
+        require!(Clock::get()?.unix_timestamp < proposal.dispute_deadline, ErrorCode::DisputeWindowClosed);
+
+        if proposal.bond > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.disputer_token_account.to_account_info(),
+                        to: ctx.accounts.bond_vault.to_account_info(),
+                        authority: ctx.accounts.disputer.to_account_info(),
+                    },
+                ),
+                proposal.bond
+            )?;
+        }
+
+        proposal.disputer = Some(ctx.accounts.disputer.key());
+
+        emit!(ResolutionDisputed {
+            market: market_key,
+            disputer: ctx.accounts.disputer.key(),
+            bond: proposal.bond
         });
         Ok(())
     }
 
-    /// Claim winnings after the end of the market
-    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
+    /// Finalize an undisputed proposal once the dispute window is over (permissionless).
+    /// The proposer gets their bond back.
+    pub fn finalize_resolution(ctx: Context<FinalizeResolution>) -> Result<()> {
         let market = &mut ctx.accounts.market;
-        let position = &mut ctx.accounts.position;
-        let protocol = &ctx.accounts.protocol;
-
-        require!(market.resolved, ErrorCode::MarketNotResolved);
-        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
-        require!(!position.claimed, ErrorCode::AlreadyClaimed);
-        require!(position.amount > 0, ErrorCode::InvalidAmount);
-
-        // Check if user bet on winning outcome
-        let winning_outcome = market.winning_outcome.ok_or(ErrorCode::MarketNotResolved)?;
-        require!(position.outcome == winning_outcome, ErrorCode::InvalidOutcome);
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
 
-        let winner_pool = market.outcome_pools[winning_outcome as usize];
-        let loser_pool = market.outcome_pools[1 - winning_outcome as usize];
+        let proposal = market.proposal.ok_or(ErrorCode::NoResolutionProposed)?;
+        require!(proposal.disputer.is_none(), ErrorCode::ResolutionDisputed);
+        require!(Clock::get()?.unix_timestamp >= proposal.dispute_deadline, ErrorCode::DisputeWindowOpen);
+        require_keys_eq!(ctx.accounts.proposer_token_account.owner, proposal.proposer, ErrorCode::Unauthorized);
 
-        require!(winner_pool > 0, ErrorCode::NoWinnersRemaining);
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
 
-        // User's share of losing pool = (position_amount / winner_pool) * loser_pool
-        let user_share = (position.amount as u128)
-            .checked_mul(loser_pool as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(winner_pool as u128).ok_or(ErrorCode::AmountOverflow)?;
+        if proposal.bond > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.bond_vault.to_account_info(),
+                        to: ctx.accounts.proposer_token_account.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                proposal.bond
+            )?;
+        }
 
-        // Total payout = original stake + winnings
-        let gross_payout = (position.amount as u128)
-            .checked_add(user_share).ok_or(ErrorCode::AmountOverflow)?;
+        apply_resolution(market, proposal.outcome, proposal.value)
+    }
 
-        require!(gross_payout <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let gross_payout_u64 = gross_payout as u64;
+    /// Settle a disputed resolution (protocol authority or arbiter). The side that matches the
+    /// final result gets both bonds back, the other side's bond is slashed. Passing neither an
+    /// outcome nor a value voids the market, and the disputer takes the bonds.
+    pub fn arbitrate_dispute(
+        ctx: Context<ArbitrateDispute>,
+        winning_outcome: Option<u8>,
+        value: Option<i64>,
+    ) -> Result<()> {
+        let protocol = &ctx.accounts.protocol;
+        let arbiter = ctx.accounts.arbiter.key();
+        require!(arbiter == protocol.authority || arbiter == protocol.arbiter, ErrorCode::Unauthorized);
 
-        // Apply protocol fee
-        let protocol_fee = gross_payout
-            .checked_mul(protocol.protocol_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
-            .checked_div(10_000).ok_or(ErrorCode::AmountOverflow)?;
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
 
-        require!(protocol_fee <= u64::MAX as u128, ErrorCode::AmountOverflow);
-        let protocol_fee_u64 = protocol_fee as u64;
+        let proposal = market.proposal.ok_or(ErrorCode::NoResolutionProposed)?;
+        let disputer = proposal.disputer.ok_or(ErrorCode::NotDisputed)?;
+        let void = winning_outcome.is_none() && value.is_none();
+
+        match market.market_type {
+            _ if void => require!(market.pending_fills == 0, ErrorCode::UnsettledFills),
+            MarketType::Categorical => {
+                let outcome = winning_outcome.ok_or(ErrorCode::InvalidOutcome)?;
+                require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+                require!(value.is_none(), ErrorCode::InvalidMarketType);
+            }
+            MarketType::Scalar { .. } => {
+                require!(value.is_some(), ErrorCode::InvalidMarketType);
+                require!(winning_outcome.is_none(), ErrorCode::InvalidOutcome);
+            }
+        }
 
-        let net_payout = gross_payout_u64.checked_sub(protocol_fee_u64)
-            .ok_or(ErrorCode::AmountOverflow)?;
+        let proposer_won = proposal.outcome == winning_outcome && proposal.value == value;
+        let winner = if proposer_won { proposal.proposer } else { disputer };
+        require_keys_eq!(ctx.accounts.winner_token_account.owner, winner, ErrorCode::Unauthorized);
 
-        // Transfer winnings
+        let bonds = proposal.bond.checked_mul(2).ok_or(ErrorCode::AmountOverflow)?;
         let id_bytes = market.id.to_le_bytes();
         let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
 
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.market_escrow.to_account_info(),
-                    to: ctx.accounts.user_token_account.to_account_info(),
-                    authority: market.to_account_info(),
-                },
-                signer_seeds
-            ),
-            net_payout
-        )?;
-
-        // Distribute fees
-        distribute_fees(
-            &ctx.accounts.market_escrow,
-            &ctx.accounts.authority_fee_recipient,
-            &ctx.accounts.dev_token_account,
-            &market.to_account_info(),
-            &ctx.accounts.token_program,
-            signer_seeds,
-            protocol_fee_u64
-        )?;
+        if bonds > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.bond_vault.to_account_info(),
+                        to: ctx.accounts.winner_token_account.to_account_info(),
+                        authority: market.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                bonds
+            )?;
+        }
 
-        position.claimed = true;
+        emit!(DisputeSettled {
+            market: market.key(),
+            arbiter,
+            proposer_won,
+            winner,
+            bonds
+        });
 
-        emit!(WinningsClaimed {
-        market: market.key(),
-        position: position.key(),
-        user: position.user,
-        winnings: net_payout
-    });
+        if void {
+            market.resolved = true;
+            market.voided = true;
 
-        Ok(())
+            emit!(MarketVoided {
+                market: market.key(),
+                expired: false
+            });
+            return Ok(());
+        }
+        apply_resolution(market, winning_outcome, value)
     }
-}
 
-fn distribute_fees<'info>(
-    escrow: &Account<'info, TokenAccount>,
-    authority_account: &AccountInfo<'info>,
-    dev_account: &Account<'info, TokenAccount>,
-    authority: &AccountInfo<'info>,
-    token_program: &Program<'info, Token>,
-    signer_seeds: &[&[&[u8]]],
-    total_fee: u64,
-) -> Result<()> {
-    if total_fee == 0 {
-        return Ok(());
+    /// Resolve an oracle market from its Pyth or Switchboard feed (permissionless).
+    /// The value must be published at or after `end_time` and pass the feed's freshness checks.
+    pub fn resolve_from_oracle(ctx: Context<ResolveFromOracle>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        let clock = Clock::get()?;
+        require!(clock.unix_timestamp >= market.end_time, ErrorCode::MarketNotEnded);
+        require!(clock.unix_timestamp < market.resolution_deadline, ErrorCode::ResolutionDeadlinePassed);
+
+        let oracle_account = &ctx.accounts.oracle_account;
+        let (value, exponent, publish_time, threshold) = match market.resolution_source {
+            ResolutionSource::Pyth { price_account, feed_id, threshold, max_staleness, max_confidence_bps } => {
+                require_keys_eq!(oracle_account.key(), price_account, ErrorCode::InvalidOracleAccount);
+
+                let price = oracle::load_pyth_price(oracle_account)?;
+                require!(price.feed_id == feed_id, ErrorCode::InvalidOracleAccount);
+                oracle::validate_pyth_price(&price, market.end_time, max_staleness, max_confidence_bps)?;
+
+                (price.price as i128, price.exponent, price.publish_time, threshold)
+            }
+            ResolutionSource::Switchboard { feed, queue, authority, threshold, max_staleness_slots } => {
+                require_keys_eq!(oracle_account.key(), feed, ErrorCode::InvalidOracleAccount);
+
+                let result = oracle::load_switchboard_feed(oracle_account)?;
+                require_keys_eq!(result.queue, queue, ErrorCode::InvalidOracleAccount);
+                require_keys_eq!(result.authority, authority, ErrorCode::InvalidOracleAccount);
+                oracle::validate_switchboard_result(&result, market.end_time, clock.slot, max_staleness_slots)?;
+
+                (result.value, -oracle::SWITCHBOARD_DECIMALS, result.timestamp, threshold)
+            }
+            ResolutionSource::Creator | ResolutionSource::Committee { .. } => {
+                return err!(ErrorCode::InvalidResolutionSource)
+            }
+        };
+
+        let winning_outcome = if threshold.is_met(value, exponent)? { 0 } else { 1 };
+
+        emit!(OracleResolved {
+            market: market.key(),
+            price: value,
+            exponent,
+            publish_time,
+            winner: winning_outcome
+        });
+
+        apply_resolution(market, Some(winning_outcome), None)
+    }
+
+    /// Vote for the winning outcome of a committee market (resolvers only, one vote each).
+    /// The market resolves as soon as an outcome reaches the committee threshold.
+    pub fn submit_resolution_vote(
+        ctx: Context<SubmitResolutionVote>,
+        outcome: u8,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= market.end_time, ErrorCode::MarketNotEnded);
+        require!(now < market.resolution_deadline, ErrorCode::ResolutionDeadlinePassed);
+        require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+
+        let ResolutionSource::Committee { threshold, .. } = market.resolution_source else {
+            return err!(ErrorCode::InvalidResolutionSource);
+        };
+        let resolver = ctx.accounts.resolver.key();
+        let idx = market.resolution_source.resolver_index(&resolver).ok_or(ErrorCode::Unauthorized)?;
+
+        let tally = &mut ctx.accounts.vote_tally;
+        if tally.market == Pubkey::default() {
+            tally.market = market.key();
+            tally.bump = ctx.bumps.vote_tally;
+        }
+        require!(tally.votes[idx].is_none(), ErrorCode::AlreadyVoted);
+
+        tally.votes[idx] = Some(outcome);
+        let votes = tally.tallies[outcome as usize].checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+        tally.tallies[outcome as usize] = votes;
+
+        emit!(ResolutionVoteSubmitted {
+            market: market.key(),
+            resolver,
+            outcome,
+            votes,
+            threshold
+        });
+
+        if votes >= threshold {
+            apply_resolution(market, Some(outcome), None)?;
+        }
+        Ok(())
+    }
+
+    /// Void an unanswerable market. Every open position can then take what it paid back with `claim_refund`.
+    pub fn void_market(ctx: Context<VoidMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(market.resolution_source == ResolutionSource::Creator, ErrorCode::InvalidResolutionSource);
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(market.proposal.is_none(), ErrorCode::ResolutionPending);
+        require!(market.pending_fills == 0, ErrorCode::UnsettledFills);
+
+        market.resolved = true;
+        market.voided = true;
+
+        emit!(MarketVoided {
+            market: market.key(),
+            expired: false
+        });
+        Ok(())
+    }
+
+    /// Void a market whose creator never resolved it before `resolution_deadline` (permissionless).
+    /// A disputed proposal nobody arbitrated can be voided one dispute window later; the proposer
+    /// and the disputer each get their bond back.
+    pub fn void_expired_market(ctx: Context<VoidExpiredMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(!market.resolved, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        let void_deadline = market.void_deadline(ctx.accounts.protocol.dispute_window)?;
+        require!(Clock::get()?.unix_timestamp >= void_deadline, ErrorCode::ResolutionDeadlineNotReached);
+        require!(market.pending_fills == 0, ErrorCode::UnsettledFills);
+
+        if let Some(proposal) = market.proposal {
+            if proposal.bond > 0 {
+                let bond_vault = ctx.accounts.bond_vault.as_ref().ok_or(ErrorCode::MissingBondAccounts)?;
+                let proposer_token_account = ctx.accounts.proposer_token_account.as_ref().ok_or(ErrorCode::MissingBondAccounts)?;
+                let disputer_token_account = ctx.accounts.disputer_token_account.as_ref().ok_or(ErrorCode::MissingBondAccounts)?;
+                require_keys_eq!(proposer_token_account.owner, proposal.proposer, ErrorCode::Unauthorized);
+                require_keys_eq!(disputer_token_account.owner, proposal.disputer.ok_or(ErrorCode::NotDisputed)?, ErrorCode::Unauthorized);
+
+                let id_bytes = market.id.to_le_bytes();
+                let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+                for to in [proposer_token_account, disputer_token_account] {
+                    token::transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            token::Transfer {
+                                from: bond_vault.to_account_info(),
+                                to: to.to_account_info(),
+                                authority: market.to_account_info(),
+                            },
+                            signer_seeds
+                        ),
+                        proposal.bond
+                    )?;
+                }
+            }
+        }
+
+        market.resolved = true;
+        market.voided = true;
+
+        emit!(MarketVoided {
+            market: market.key(),
+            expired: true
+        });
+        Ok(())
+    }
+
+    /// Hand a position over to another owner. The Position PDA is derived from its owner, so the
+    /// old account is closed and re-created under the new owner with the same id and stake.
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        let position = &ctx.accounts.position;
+        let new_owner = ctx.accounts.new_owner.key();
+
+        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
+        require!(!position.claimed, ErrorCode::AlreadyClaimed);
+        require!(position.amount > 0, ErrorCode::InvalidAmount);
+        require!(position.locked == 0, ErrorCode::PositionLocked);
+        // Orders, buy orders included, point at the position's address until they are cancelled
+        require!(position.open_orders == 0, ErrorCode::PositionHasOpenOrders);
+        // Asset-backed positions move with their asset
+        require!(position.asset.is_none(), ErrorCode::PositionHasAsset);
+        // Book orders and queued fills point at the position's address
+        let market = &ctx.accounts.market;
+        require!(
+            market.pricing != PricingMode::OrderBook || market.resolved || market.emergency,
+            ErrorCode::UnsupportedPricingMode
+        );
+        require_keys_neq!(new_owner, position.user, ErrorCode::InvalidNewOwner);
+
+        let new_position = &mut ctx.accounts.new_position;
+        new_position.id = position.id;
+        new_position.bump = ctx.bumps.new_position;
+        new_position.user = new_owner;
+        new_position.market = position.market;
+        new_position.outcome = position.outcome;
+        new_position.amount = position.amount;
+        new_position.claimed = false;
+        new_position.ts = position.ts;
+        new_position.asset = None;
+        new_position.locked = 0;
+        new_position.cost = position.cost;
+        new_position.open_orders = 0;
+
+        emit!(PositionTransferred {
+            market: position.market,
+            old_position: position.key(),
+            new_position: new_position.key(),
+            from: position.user,
+            to: new_owner,
+            amount: position.amount
+        });
+        Ok(())
+    }
+
+    /// Take back the collateral a position paid for its shares in a voided market (no protocol fee),
+    /// pro rata from the escrow if it no longer covers what every position paid (`Market::refund_value`).
+    /// Outcome tokens are burned and refunded at their outcome's average cost.
+    pub fn claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(market.voided, ErrorCode::MarketNotVoided);
+
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        let (idx, amount, cost) = match tokens {
+            Some((_, user_outcome_account, outcome)) => {
+                let amount = user_outcome_account.amount;
+                (outcome, amount, market.average_cost(outcome, amount)?)
+            }
+            None => {
+                let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+                require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+                require!(!position.claimed, ErrorCode::AlreadyClaimed);
+                require!(position.locked == 0, ErrorCode::PositionLocked);
+                (position.outcome as usize, position.amount, position.cost)
+            }
+        };
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let refund = market.refund_value(cost, ctx.accounts.market_escrow.amount)?;
+        let cost = match tokens {
+            Some((outcome_mint, user_outcome_account, _)) => {
+                burn_outcome_tokens(outcome_mint, user_outcome_account, &ctx.accounts.user, &ctx.accounts.token_program, amount)?;
+                cost
+            }
+            None => {
+                let position = ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?;
+                position.claimed = true;
+                position.release(amount)?
+            }
+        };
+        market.release_shares(idx, amount, cost)?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            refund
+        )?;
+
+        if tokens.is_some() {
+            emit!(OutcomeTokensRedeemed {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome: idx as u8,
+                amount,
+                payout: refund,
+                fee: 0
+            });
+            return Ok(());
+        }
+
+        let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+        burn_position_asset(position, &ctx.accounts.position_asset, &ctx.accounts.mpl_core_program, &ctx.accounts.user)?;
+
+        emit!(RefundClaimed {
+            market: market.key(),
+            position: position.key(),
+            user: position.user,
+            amount,
+            refund
+        });
+        Ok(())
+    }
+
+    /// Claim winnings after the end of the market. In an outcome token market the winning tokens
+    /// in `user_outcome_account` are burned and paid out instead.
+    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let protocol = &ctx.accounts.protocol;
+
+        require_keys_eq!(
+            ctx.accounts.authority_fee_recipient.key(),
+            protocol.authority_fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+        require!(market.resolved, ErrorCode::MarketNotResolved);
+        require!(!market.voided, ErrorCode::MarketVoided);
+        if let Some(condition) = market.parent {
+            require_condition(condition, ctx.accounts.parent_market.as_ref())?;
+        }
+
+        let tokens = outcome_token_accounts(market, &ctx.accounts.outcome_mint, &ctx.accounts.user_outcome_account)?;
+        let (outcome, amount) = match tokens {
+            Some((_, user_outcome_account, outcome)) => (outcome as u8, user_outcome_account.amount),
+            None => {
+                let position = ctx.accounts.position.as_ref().ok_or(ErrorCode::MissingPosition)?;
+                require_position_owner(position, &ctx.accounts.user.key(), ctx.accounts.position_asset.as_ref())?;
+                require!(!position.claimed, ErrorCode::AlreadyClaimed);
+                require!(position.locked == 0, ErrorCode::PositionLocked);
+                (position.outcome, position.amount)
+            }
+        };
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let gross_payout_u64 = market.winnings(outcome, amount)?;
+        let gross_payout = gross_payout_u64 as u128;
+
+        // Apply protocol fee
+        let protocol_fee = gross_payout
+            .checked_mul(protocol.protocol_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(10_000).ok_or(ErrorCode::AmountOverflow)?;
+
+        require!(protocol_fee <= u64::MAX as u128, ErrorCode::AmountOverflow);
+        let protocol_fee_u64 = protocol_fee as u64;
+
+        let net_payout = gross_payout_u64.checked_sub(protocol_fee_u64)
+            .ok_or(ErrorCode::AmountOverflow)?;
+
+        if let Some((outcome_mint, user_outcome_account, _)) = tokens {
+            burn_outcome_tokens(outcome_mint, user_outcome_account, &ctx.accounts.user, &ctx.accounts.token_program, amount)?;
+        }
+
+        // Transfer winnings
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            net_payout
+        )?;
+
+        // Distribute fees
+        distribute_fees(
+            &ctx.accounts.market_escrow,
+            ctx.accounts.protocol_token_account.as_ref(),
+            ctx.accounts.dev_token_account.as_ref(),
+            &market.to_account_info(),
+            &ctx.accounts.token_program,
+            signer_seeds,
+            protocol_fee_u64,
+            protocol.dev_fee_share_bps
+        )?;
+
+        if tokens.is_some() {
+            emit!(OutcomeTokensRedeemed {
+                market: market.key(),
+                user: ctx.accounts.user.key(),
+                outcome,
+                amount,
+                payout: net_payout,
+                fee: protocol_fee_u64
+            });
+            return Ok(());
+        }
+
+        let position = ctx.accounts.position.as_mut().ok_or(ErrorCode::MissingPosition)?;
+        position.claimed = true;
+
+        burn_position_asset(position, &ctx.accounts.position_asset, &ctx.accounts.mpl_core_program, &ctx.accounts.user)?;
+
+        emit!(WinningsClaimed {
+        market: market.key(),
+        position: position.key(),
+        user: position.user,
+        winnings: net_payout
+    });
+
+        Ok(())
+    }
+
+    /// Create the SPL mints of every outcome at once (creator only, before any bet), so a market
+    /// never ends up with only some of them. Pass the `["outcome_mint", market, outcome]` PDAs in
+    /// outcome order as remaining accounts. The market then trades outcome tokens instead of Positions.
+    pub fn init_outcome_mints<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitOutcomeMints<'info>>,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        // Seed and LP liquidity may already be in the pools, bets may not
+        require!(market.position_count == 0, ErrorCode::MarketHasBets);
+        require!(market.pricing != PricingMode::OrderBook, ErrorCode::UnsupportedPricingMode);
+        require!(market.parent.is_none(), ErrorCode::ConditionalMarket);
+        require!(market.outcome_mint_count == 0, ErrorCode::OutcomeTokenMarket);
+        require!(ctx.remaining_accounts.len() == market.outcomes.len(), ErrorCode::InvalidOutcomeAccounts);
+
+        let market_key = market.key();
+        for (outcome, mint) in ctx.remaining_accounts.iter().enumerate() {
+            create_outcome_mint(
+                &market_key,
+                outcome as u8,
+                mint,
+                &ctx.accounts.creator,
+                ctx.accounts.token_mint.decimals,
+                &ctx.accounts.token_program,
+                &ctx.accounts.system_program,
+            )?;
+
+            emit!(OutcomeMintCreated {
+                market: market_key,
+                outcome: outcome as u8,
+                mint: mint.key()
+            });
+        }
+        market.outcome_mint_count = market.outcomes.len() as u8;
+        Ok(())
+    }
+
+    /// Deposit `amount` of collateral and mint `amount` of every outcome token (a complete set).
+    /// The sets do not touch the AMM pools, so there is no price impact and no fee; their tokens
+    /// are shares like any other and can be sold, claimed or refunded.
+    pub fn split_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SplitCollateral<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+        require!(market.has_outcome_tokens(), ErrorCode::NotOutcomeTokenMarket);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+
+        let outcome_accounts = outcome_set_accounts(market, &ctx.accounts.user.key(), ctx.remaining_accounts)?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.market_escrow.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount
+        )?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        for (mint, holder) in outcome_accounts {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::MintTo {
+                        mint: mint.clone(),
+                        to: holder.clone(),
+                        authority: market.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                amount
+            )?;
+        }
+
+        market.split_sets(amount)?;
+
+        emit!(CollateralSplit {
+            market: market.key(),
+            user: ctx.accounts.user.key(),
+            amount
+        });
+        Ok(())
+    }
+
+    /// Burn `amount` of every outcome token and take back `amount` of collateral at par, whichever
+    /// way the tokens were acquired. Once a market is voided or in emergency mode the set is
+    /// refunded at the average cost of its outcomes instead, as refunding each token would.
+    pub fn merge_positions<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MergePositions<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+        require!(market.has_outcome_tokens(), ErrorCode::NotOutcomeTokenMarket);
+        let refunded = market.voided || market.emergency;
+        require!(!market.resolved || refunded, ErrorCode::MarketResolved);
+
+        let outcome_accounts = outcome_set_accounts(market, &ctx.accounts.user.key(), ctx.remaining_accounts)?;
+
+        for (mint, holder) in outcome_accounts {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: mint.clone(),
+                        from: holder.clone(),
+                        authority: ctx.accounts.user.to_account_info(),
+                    },
+                ),
+                amount
+            )?;
+        }
+
+        let payout = if refunded {
+            market.refund_value(market.set_cost(amount)?, ctx.accounts.market_escrow.amount)?
+        } else {
+            amount
+        };
+        market.merge_sets(amount)?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            payout
+        )?;
+
+        emit!(PositionsMerged {
+            market: market.key(),
+            user: ctx.accounts.user.key(),
+            amount,
+            payout
+        });
+        Ok(())
+    }
+
+    /// Deposit collateral as AMM liquidity without moving the prices. LP shares are minted as
+    /// liquidity units against the value of the providers' shares at the current prices plus fees.
+    pub fn add_liquidity(
+        ctx: Context<AddLiquidity>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+        require!(market.pricing == PricingMode::ConstantProduct, ErrorCode::UnsupportedPricingMode);
+        require!(market.parent.is_none(), ErrorCode::ConditionalMarket);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketEnded);
+
+        let shares = market.add_liquidity(amount)?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.market_escrow.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount
+        )?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.user_lp_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            shares
+        )?;
+
+        emit!(LiquidityAdded {
+            market: market.key(),
+            provider: ctx.accounts.user.key(),
+            amount,
+            shares
+        });
+        Ok(())
+    }
+
+    /// Burn LP shares for their part of the liquidity and accrued fees. Before `end_time` the
+    /// shares they own are merged and sold back to the pools with the usual sale fees; after
+    /// resolution they pay like positions, less the protocol fee. In a voided market traders are
+    /// refunded first and providers share what is left. Liquidity is locked between `end_time`
+    /// and resolution.
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        shares: u64,
+        min_payout: u64,
+    ) -> Result<()> {
+        require!(shares > 0 && shares <= ctx.accounts.user_lp_account.amount, ErrorCode::InvalidAmount);
+
+        let protocol = &ctx.accounts.protocol;
+        require_keys_eq!(
+            ctx.accounts.authority_fee_recipient.key(),
+            protocol.authority_fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+
+        let market = &mut ctx.accounts.market;
+        let settled = market.resolved || market.emergency;
+        require!(settled || Clock::get()?.unix_timestamp < market.end_time, ErrorCode::LiquidityLocked);
+        require!(settled || !market.paused, ErrorCode::MarketPaused);
+
+        let refunded = market.voided || market.emergency;
+        let units = market.lp_shares;
+        let residual = if refunded { market.residual_escrow(ctx.accounts.market_escrow.amount)? } else { 0 };
+        if !settled {
+            market.record_prices(Clock::get()?.unix_timestamp)?;
+        }
+        let (held, fees) = market.take_liquidity(shares)?;
+
+        let (stakes_value, protocol_fee) = if refunded {
+            let refund = (residual as u128)
+                .checked_mul(shares as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(units as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+            (refund, 0)
+        } else if market.resolved {
+            // Settled liquidity pays the protocol fee like winning positions
+            let value = market.settled_value(&held)?;
+            let fee = (value as u128)
+                .checked_mul(protocol.protocol_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+            (value.checked_sub(fee).ok_or(ErrorCode::AmountOverflow)?, fee)
+        } else {
+            market.exit_liquidity(&held, protocol.amm_fee, protocol.cancel_fee_bps)?
+        };
+
+        let payout = stakes_value.checked_add(fees).ok_or(ErrorCode::AmountOverflow)?;
+        require!(payout >= min_payout, ErrorCode::SlippageExceeded);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            shares
+        )?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            payout
+        )?;
+
+        distribute_fees(
+            &ctx.accounts.market_escrow,
+            ctx.accounts.protocol_token_account.as_ref(),
+            ctx.accounts.dev_token_account.as_ref(),
+            &market.to_account_info(),
+            &ctx.accounts.token_program,
+            signer_seeds,
+            protocol_fee,
+            protocol.dev_fee_share_bps
+        )?;
+
+        emit!(LiquidityRemoved {
+            market: market.key(),
+            provider: ctx.accounts.user.key(),
+            shares,
+            payout,
+            fees
+        });
+        Ok(())
+    }
+
+    /// Return the creator's seed liquidity once the market is settled: the shares it owns pay like
+    /// positions, less the protocol fee, plus its part of the LP fees and, for LMSR markets, what is left of the subsidy
+    /// once every outstanding share is paid. In voided or emergency markets traders are refunded
+    /// first and the creator shares what is left with the other liquidity providers.
+    pub fn claim_seed_liquidity(ctx: Context<ClaimSeedLiquidity>) -> Result<()> {
+        let protocol = &ctx.accounts.protocol;
+        require_keys_eq!(
+            ctx.accounts.authority_fee_recipient.key(),
+            protocol.authority_fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+
+        let market = &mut ctx.accounts.market;
+        require!(market.resolved || market.emergency, ErrorCode::MarketNotResolved);
+        let stake = market.seed_shares;
+        require!(stake > 0, ErrorCode::AlreadyClaimed);
+
+        let refunded = market.voided || market.emergency;
+        if let (Some(condition), false) = (market.parent, refunded) {
+            require_condition(condition, ctx.accounts.parent_market.as_ref())?;
+        }
+
+        let units = market.lp_shares;
+        let residual = if refunded { market.residual_escrow(ctx.accounts.market_escrow.amount)? } else { 0 };
+        let (held, fees) = market.take_liquidity(stake)?;
+
+        let (seed_payout, protocol_fee) = if refunded {
+            let refund = (residual as u128)
+                .checked_mul(stake as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(units as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+            (refund, 0)
+        } else {
+            // The seed's settled shares pay the protocol fee like LP shares; the LMSR subsidy left
+            // over is the creator's own collateral
+            let value = market.settled_value(&held)?;
+            let fee = (value as u128)
+                .checked_mul(protocol.protocol_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+            let payout = value
+                .checked_sub(fee).ok_or(ErrorCode::AmountOverflow)?
+                .checked_add(market.lmsr_surplus()?).ok_or(ErrorCode::AmountOverflow)?;
+            (payout, fee)
+        };
+        let payout = seed_payout.checked_add(fees).ok_or(ErrorCode::AmountOverflow)?;
+        market.seed_shares = 0;
+        market.subsidy = 0;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.market_escrow.to_account_info(),
+                    to: ctx.accounts.creator_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            payout
+        )?;
+
+        distribute_fees(
+            &ctx.accounts.market_escrow,
+            ctx.accounts.protocol_token_account.as_ref(),
+            ctx.accounts.dev_token_account.as_ref(),
+            &market.to_account_info(),
+            &ctx.accounts.token_program,
+            signer_seeds,
+            protocol_fee,
+            protocol.dev_fee_share_bps
+        )?;
+
+        emit!(SeedLiquidityClaimed {
+            market: market.key(),
+            creator: market.creator,
+            stake,
+            payout
+        });
+        Ok(())
+    }
+
+    /// Escrow `amount` of collateral in a limit order buying `outcome` at up to `limit_price_bps`
+    /// per share. Bought shares land on a new position opened for the order.
+    pub fn place_buy_order(
+        ctx: Context<PlaceBuyOrder>,
+        outcome: u8,
+        amount: u64,
+        limit_price_bps: u16,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+        require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        validate_order(market, &ctx.accounts.protocol, limit_price_bps, expiry)?;
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.order_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount
+        )?;
+
+        let position = &mut ctx.accounts.position;
+        position.id = market.position_count;
+        position.bump = ctx.bumps.position;
+        position.user = ctx.accounts.user.key();
+        position.market = market.key();
+        position.outcome = outcome;
+        position.amount = 0;
+        position.claimed = false;
+        position.ts = Clock::get()?.unix_timestamp;
+        position.asset = None;
+        position.locked = 0;
+        position.cost = 0;
+        // The position belongs to the order until the order is cancelled
+        position.open_orders = 1;
+        market.position_count = market.position_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        let order = &mut ctx.accounts.order;
+        order.id = market.order_count;
+        order.bump = ctx.bumps.order;
+        order.market = market.key();
+        order.owner = ctx.accounts.user.key();
+        order.position = position.key();
+        order.side = OrderSide::Buy;
+        order.outcome = outcome;
+        order.limit_price_bps = limit_price_bps;
+        order.remaining = amount;
+        order.filled = 0;
+        order.expiry = expiry;
+        market.order_count = market.order_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        emit!(OrderPlaced {
+            market: market.key(),
+            order: order.key(),
+            owner: order.owner,
+            side: order.side,
+            outcome,
+            limit_price_bps,
+            amount,
+            expiry
+        });
+        Ok(())
+    }
+
+    /// Lock `amount` shares of a position in a limit order selling them at `limit_price_bps` per
+    /// share or better. Proceeds collect in the order vault until the order is cancelled.
+    pub fn place_sell_order(
+        ctx: Context<PlaceSellOrder>,
+        amount: u64,
+        limit_price_bps: u16,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let market = &mut ctx.accounts.market;
+        validate_order(market, &ctx.accounts.protocol, limit_price_bps, expiry)?;
+
+        let position = &mut ctx.accounts.position;
+        require!(position.user == ctx.accounts.user.key(), ErrorCode::PositionOwnerMismatch);
+        // Asset-backed positions can change hands while the order rests
+        require!(position.asset.is_none(), ErrorCode::PositionHasAsset);
+        require!(!position.claimed, ErrorCode::AlreadyClaimed);
+        require!(amount <= position.free()?, ErrorCode::WithdrawAmountExceedsPosition);
+        position.locked = position.locked.checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+        position.open_orders = position.open_orders.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        let order = &mut ctx.accounts.order;
+        order.id = market.order_count;
+        order.bump = ctx.bumps.order;
+        order.market = market.key();
+        order.owner = ctx.accounts.user.key();
+        order.position = position.key();
+        order.side = OrderSide::Sell;
+        order.outcome = position.outcome;
+        order.limit_price_bps = limit_price_bps;
+        order.remaining = amount;
+        order.filled = 0;
+        order.expiry = expiry;
+        market.order_count = market.order_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        emit!(OrderPlaced {
+            market: market.key(),
+            order: order.key(),
+            owner: order.owner,
+            side: order.side,
+            outcome: order.outcome,
+            limit_price_bps,
+            amount,
+            expiry
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank. Without `counter_order` the whole order executes against the pool,
+    /// provided its average price meets the limit. With one (opposite side, same outcome) the two
+    /// orders cross at the older order's limit, as far as both allow.
+    pub fn fill_order(ctx: Context<FillOrder>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let accounts = ctx.accounts;
+        let market = &mut accounts.market;
+        let protocol = &accounts.protocol;
+        let order = &mut accounts.order;
+
+        require_keys_eq!(
+            accounts.authority_fee_recipient.key(),
+            protocol.authority_fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+        require!(!protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(now < market.end_time, ErrorCode::MarketEnded);
+        require!(now < order.expiry, ErrorCode::OrderExpired);
+        require!(order.remaining > 0, ErrorCode::InvalidAmount);
+
+        if let Some(counter) = accounts.counter_order.as_mut() {
+            require!(now < counter.expiry, ErrorCode::OrderExpired);
+            require!(counter.remaining > 0, ErrorCode::InvalidAmount);
+            require!(counter.outcome == order.outcome && counter.side != order.side, ErrorCode::InvalidOrder);
+
+            let counter_vault = accounts.counter_vault.as_ref().ok_or(ErrorCode::InvalidOrder)?;
+            require_keys_eq!(counter_vault.owner, counter.key(), ErrorCode::InvalidOrder);
+            let counter_position = accounts.counter_position.as_mut().ok_or(ErrorCode::InvalidOrder)?;
+            require_keys_eq!(counter_position.key(), counter.position, ErrorCode::InvalidOrder);
+
+            // The older order was resting first and sets the price
+            let price_bps = if order.id < counter.id { order.limit_price_bps } else { counter.limit_price_bps };
+            let (shares, collateral) = match order.side {
+                OrderSide::Buy => cross_orders(
+                    market,
+                    order, &accounts.order_vault, &mut accounts.position,
+                    counter, counter_vault, counter_position,
+                    price_bps, &accounts.token_program
+                )?,
+                OrderSide::Sell => cross_orders(
+                    market,
+                    counter, counter_vault, counter_position,
+                    order, &accounts.order_vault, &mut accounts.position,
+                    price_bps, &accounts.token_program
+                )?,
+            };
+
+            emit!(OrderFilled {
+                market: market.key(),
+                order: order.key(),
+                counter_order: Some(counter.key()),
+                shares,
+                collateral
+            });
+            return Ok(());
+        }
+
+        let idx = order.outcome as usize;
+        let limit = order.limit_price_bps as u128;
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+
+        let (shares, collateral) = match order.side {
+            OrderSide::Buy => {
+                let collateral = order.remaining;
+                let quote = market.buy_quote(idx, collateral, protocol.amm_fee)?;
+                let shares = quote.shares;
+                // Average price of the fill must not exceed the limit
+                require!(
+                    shares > 0 && collateral as u128 * BPS_DENOMINATOR as u128 <= limit * shares as u128,
+                    ErrorCode::OrderNotFillable
+                );
+
+                let order_id = order.id.to_le_bytes();
+                let order_bump = [order.bump];
+                let order_seeds = order.signer_seeds(&order_id, &order_bump);
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: accounts.order_vault.to_account_info(),
+                            to: accounts.market_escrow.to_account_info(),
+                            authority: order.to_account_info(),
+                        },
+                        &[&order_seeds[..]]
+                    ),
+                    collateral
+                )?;
+
+                market.record_prices(now)?;
+                market.apply_buy(idx, collateral, &quote)?;
+                let position = &mut accounts.position;
+                position.amount = position.amount.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+                position.cost = position.cost.checked_add(collateral).ok_or(ErrorCode::AmountOverflow)?;
+                order.filled = order.filled.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+                (shares, collateral)
+            }
+            OrderSide::Sell => {
+                let shares = order.remaining;
+                let quote = market.sell_quote(idx, shares, protocol.amm_fee, protocol.cancel_fee_bps)?;
+                // Average price of the fill, after fees, must reach the limit
+                require!(
+                    quote.payout_net as u128 * BPS_DENOMINATOR as u128 >= limit * shares as u128,
+                    ErrorCode::OrderNotFillable
+                );
+
+                let position = &mut accounts.position;
+                let cost = position.release(shares)?;
+                position.locked = position.locked.checked_sub(shares).ok_or(ErrorCode::AmountOverflow)?;
+                market.record_prices(now)?;
+                let protocol_fee = market.apply_sell(idx, shares, cost, &quote)?;
+
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: accounts.market_escrow.to_account_info(),
+                            to: accounts.order_vault.to_account_info(),
+                            authority: market.to_account_info(),
+                        },
+                        signer_seeds
+                    ),
+                    quote.payout_net
+                )?;
+
+                distribute_fees(
+                    &accounts.market_escrow,
+                    accounts.protocol_token_account.as_ref(),
+                    accounts.dev_token_account.as_ref(),
+                    &market.to_account_info(),
+                    &accounts.token_program,
+                    signer_seeds,
+                    protocol_fee,
+                    protocol.dev_fee_share_bps
+                )?;
+
+                order.filled = order.filled.checked_add(quote.payout_net).ok_or(ErrorCode::AmountOverflow)?;
+                (shares, quote.payout_net)
+            }
+        };
+        order.remaining = 0;
+
+        emit!(OrderFilled {
+            market: market.key(),
+            order: order.key(),
+            counter_order: None,
+            shares,
+            collateral
+        });
+        Ok(())
+    }
+
+    /// Close an order: the vault balance (unspent collateral or sale proceeds) goes back to the
+    /// owner and unsold shares are unlocked. Anyone can cancel once the order can no longer fill.
+    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+
+        let fillable = now < order.expiry && now < market.end_time && !market.resolved && !market.emergency;
+        require!(ctx.accounts.caller.key() == order.owner || !fillable, ErrorCode::Unauthorized);
+
+        let shares = if order.side == OrderSide::Sell { order.remaining } else { 0 };
+        let position = &mut ctx.accounts.position;
+        position.locked = position.locked.checked_sub(shares).ok_or(ErrorCode::AmountOverflow)?;
+        position.open_orders = position.open_orders.checked_sub(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        let order_id = order.id.to_le_bytes();
+        let order_bump = [order.bump];
+        let order_seeds = order.signer_seeds(&order_id, &order_bump);
+        let signer_seeds: &[&[&[u8]]] = &[&order_seeds[..]];
+
+        let refund = ctx.accounts.order_vault.amount;
+        if refund > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.order_vault.to_account_info(),
+                        to: ctx.accounts.owner_token_account.to_account_info(),
+                        authority: order.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                refund
+            )?;
+        }
+
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.order_vault.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: order.to_account_info(),
+            },
+            signer_seeds
+        ))?;
+
+        emit!(OrderCancelled {
+            market: market.key(),
+            order: order.key(),
+            owner: order.owner,
+            refund,
+            shares
+        });
+        Ok(())
+    }
+
+    /// Open the order book of an `OrderBook` market (creator only, once).
+    pub fn init_order_book(ctx: Context<InitOrderBook>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(market.pricing == PricingMode::OrderBook, ErrorCode::UnsupportedPricingMode);
+
+        let order_book = &mut ctx.accounts.order_book;
+        order_book.market = market.key();
+        order_book.bump = ctx.bumps.order_book;
+        order_book.order_count = 0;
+        order_book.bids = Vec::new();
+        order_book.asks = Vec::new();
+        order_book.events = Vec::new();
+
+        emit!(OrderBookCreated {
+            market: market.key(),
+            order_book: order_book.key()
+        });
+        Ok(())
+    }
+
+    /// Bid for (or ask, i.e. bid for the second outcome at the complement) `quantity` shares of
+    /// the first outcome at `price_bps`. The order first matches resting orders at their prices
+    /// and the rest goes on the book. Shares land on a new position once the crank settles them.
+    pub fn place_book_order(
+        ctx: Context<PlaceBookOrder>,
+        side: BookSide,
+        price_bps: u16,
+        quantity: u64,
+    ) -> Result<()> {
+        require!(quantity > 0 && quantity % BOOK_LOT_SIZE == 0, ErrorCode::InvalidAmount);
+        require!(price_bps > 0 && price_bps < BPS_DENOMINATOR, ErrorCode::InvalidOrder);
+
+        let market = &mut ctx.accounts.market;
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(Clock::get()?.unix_timestamp < market.end_time, ErrorCode::MarketEnded);
+
+        let position = &mut ctx.accounts.position;
+        position.id = market.position_count;
+        position.bump = ctx.bumps.position;
+        position.user = ctx.accounts.user.key();
+        position.market = market.key();
+        position.outcome = side.outcome();
+        position.amount = 0;
+        position.claimed = false;
+        position.ts = Clock::get()?.unix_timestamp;
+        position.asset = None;
+        position.locked = 0;
+        position.cost = 0;
+        position.open_orders = 0;
+        market.position_count = market.position_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        let order_book = &mut ctx.accounts.order_book;
+        let order_id = order_book.order_count;
+        order_book.order_count = order_book.order_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        let queued = order_book.events.len();
+        let (filled, fill_cost) = order_book.match_order(side, price_bps, quantity, position.key())?;
+        for event in &order_book.events[queued..] {
+            emit!(BookOrderMatched {
+                market: market.key(),
+                maker_order: event.maker_order,
+                taker_order: order_id,
+                price_bps: event.price_bps,
+                quantity: event.quantity
+            });
+        }
+        market.pending_fills = market.pending_fills
+            .checked_add((order_book.events.len() - queued) as u64).ok_or(ErrorCode::AmountOverflow)?;
+
+        let resting = quantity - filled;
+        if resting > 0 {
+            order_book.insert(side, BookOrder {
+                id: order_id,
+                owner: ctx.accounts.user.key(),
+                position: position.key(),
+                price_bps,
+                quantity: resting,
+            })?;
+        }
+
+        // Fills are paid at the makers' prices, the resting part at our own
+        let cost = fill_cost
+            .checked_add(side.collateral(price_bps, resting)?).ok_or(ErrorCode::AmountOverflow)?;
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.book_vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            cost
+        )?;
+
+        emit!(BookOrderPlaced {
+            market: market.key(),
+            order_id,
+            owner: ctx.accounts.user.key(),
+            position: position.key(),
+            side,
+            price_bps,
+            quantity,
+            filled,
+            cost
+        });
+        Ok(())
+    }
+
+    /// Take a resting order off the book and refund its collateral. The owner can cancel at any
+    /// time, anyone once the market stops trading. Shares already matched stay on the position.
+    pub fn cancel_book_order(ctx: Context<CancelBookOrder>, order_id: u64) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let now = Clock::get()?.unix_timestamp;
+        let trading = now < market.end_time && !market.resolved && !market.emergency;
+
+        let (side, order) = ctx.accounts.order_book.remove(order_id)?;
+        require_keys_eq!(ctx.accounts.owner.key(), order.owner, ErrorCode::InvalidOrder);
+        require!(ctx.accounts.caller.key() == order.owner || !trading, ErrorCode::Unauthorized);
+
+        let refund = side.collateral(order.price_bps, order.quantity)?;
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.book_vault.to_account_info(),
+                    to: ctx.accounts.owner_token_account.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            refund
+        )?;
+
+        emit!(BookOrderCancelled {
+            market: market.key(),
+            order_id,
+            owner: order.owner,
+            quantity: order.quantity,
+            refund
+        });
+        Ok(())
+    }
+
+    /// Settle up to `limit` queued fills, oldest first (permissionless crank). Pass the positions
+    /// of the fills as writable remaining accounts. Each fill credits both positions and moves one
+    /// unit of collateral per share from the book vault to the market escrow.
+    pub fn consume_book_events<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ConsumeBookEvents<'info>>,
+        limit: u8,
+    ) -> Result<()> {
+        let mut positions = ctx.remaining_accounts
+            .iter()
+            .map(Account::<Position>::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        require!(
+            positions.iter().enumerate().all(|(i, p)| positions[..i].iter().all(|q| q.key() != p.key())),
+            ErrorCode::InvalidOrder
+        );
+
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        let mut fills = 0usize;
+        let mut shares = 0u64;
+        for event in order_book.events.iter().take(limit as usize) {
+            let bid = positions.iter().position(|p| p.key() == event.bid_position);
+            let ask = positions.iter().position(|p| p.key() == event.ask_position);
+            let (Some(bid), Some(ask)) = (bid, ask) else { break };
+
+            // Each side's cost is what it paid at the fill price, the two summing to one unit per share
+            let bid_cost = BookSide::Bid.collateral(event.price_bps, event.quantity)?;
+            let ask_cost = BookSide::Ask.collateral(event.price_bps, event.quantity)?;
+            for (i, cost) in [(bid, bid_cost), (ask, ask_cost)] {
+                positions[i].amount = positions[i].amount.checked_add(event.quantity).ok_or(ErrorCode::AmountOverflow)?;
+                positions[i].cost = positions[i].cost.checked_add(cost).ok_or(ErrorCode::AmountOverflow)?;
+                market.hold_shares(positions[i].outcome as usize, event.quantity, cost)?;
+            }
+            shares = shares.checked_add(event.quantity).ok_or(ErrorCode::AmountOverflow)?;
+            fills += 1;
+        }
+        require!(fills > 0, ErrorCode::NoFillsSettled);
+        order_book.events.drain(..fills);
+
+        for position in &positions {
+            position.exit(&crate::ID)?;
+        }
+
+        // Every share of the first outcome was bought together with one of the second
+        market.total_volume = market.total_volume.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+        market.pending_fills = market.pending_fills.checked_sub(fills as u64).ok_or(ErrorCode::AmountOverflow)?;
+
+        let id_bytes = market.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"market", id_bytes.as_ref(), &[market.bump]]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.book_vault.to_account_info(),
+                    to: ctx.accounts.market_escrow.to_account_info(),
+                    authority: market.to_account_info(),
+                },
+                signer_seeds
+            ),
+            shares
+        )?;
+
+        emit!(BookFillsSettled {
+            market: market.key(),
+            fills: fills as u64,
+            shares,
+            pending_fills: market.pending_fills
+        });
+        Ok(())
     }
 
-    let half = total_fee / 2;
-    let remainder = total_fee - half;
+    /// Challenge another wallet: escrow `stake` on `outcome` against a counter-stake of
+    /// `stake * odds_numerator / odds_denominator` on every other outcome. Only `taker` can accept
+    /// when set, anyone otherwise, until `expiry`. Offers never touch the market pools.
+    pub fn make_offer(
+        ctx: Context<MakeOffer>,
+        outcome: u8,
+        stake: u64,
+        odds_numerator: u32,
+        odds_denominator: u32,
+        taker: Option<Pubkey>,
+        expiry: i64,
+    ) -> Result<()> {
+        require!(stake > 0, ErrorCode::InvalidAmount);
+        require!(odds_numerator > 0 && odds_denominator > 0, ErrorCode::InvalidOffer);
 
-    token::transfer(
-        CpiContext::new_with_signer(
+        let market = &mut ctx.accounts.market;
+        require!(market.market_type == MarketType::Categorical, ErrorCode::InvalidMarketType);
+        require!(market.parent.is_none(), ErrorCode::ConditionalMarket);
+        require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < market.end_time, ErrorCode::MarketEnded);
+        require!(expiry > now, ErrorCode::OfferExpired);
+        require!(taker != Some(ctx.accounts.maker.key()), ErrorCode::InvalidOffer);
+
+        let taker_stake: u64 = (stake as u128)
+            .checked_mul(odds_numerator as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(odds_denominator as u128).ok_or(ErrorCode::AmountOverflow)?
+            .try_into().map_err(|_| ErrorCode::AmountOverflow)?;
+        require!(taker_stake > 0, ErrorCode::InvalidOffer);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.maker_token_account.to_account_info(),
+                    to: ctx.accounts.offer_vault.to_account_info(),
+                    authority: ctx.accounts.maker.to_account_info(),
+                },
+            ),
+            stake
+        )?;
+
+        let offer = &mut ctx.accounts.offer;
+        offer.id = market.offer_count;
+        offer.bump = ctx.bumps.offer;
+        offer.market = market.key();
+        offer.maker = ctx.accounts.maker.key();
+        offer.taker = taker;
+        offer.outcome = outcome;
+        offer.maker_stake = stake;
+        offer.taker_stake = taker_stake;
+        offer.expiry = expiry;
+        offer.accepted = false;
+        market.offer_count = market.offer_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        emit!(OfferMade {
+            market: market.key(),
+            offer: offer.key(),
+            maker: offer.maker,
+            taker,
+            outcome,
+            maker_stake: stake,
+            taker_stake,
+            expiry
+        });
+        Ok(())
+    }
+
+    /// Take the other side of an offer by escrowing its counter-stake.
+    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let offer = &mut ctx.accounts.offer;
+        let taker = ctx.accounts.taker.key();
+
+        require!(!offer.accepted, ErrorCode::OfferAlreadyAccepted);
+        require!(offer.taker.is_none_or(|t| t == taker), ErrorCode::Unauthorized);
+        require_keys_neq!(taker, offer.maker, ErrorCode::InvalidOffer);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+        require!(!market.paused, ErrorCode::MarketPaused);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < market.end_time, ErrorCode::MarketEnded);
+        require!(now < offer.expiry, ErrorCode::OfferExpired);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.taker_token_account.to_account_info(),
+                    to: ctx.accounts.offer_vault.to_account_info(),
+                    authority: ctx.accounts.taker.to_account_info(),
+                },
+            ),
+            offer.taker_stake
+        )?;
+
+        offer.taker = Some(taker);
+        offer.accepted = true;
+
+        emit!(OfferAccepted {
+            market: market.key(),
+            offer: offer.key(),
+            taker,
+            taker_stake: offer.taker_stake
+        });
+        Ok(())
+    }
+
+    /// Withdraw an offer nobody accepted and refund the maker. The maker can cancel at any time,
+    /// anyone once it expired or the market stopped trading.
+    pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let offer = &ctx.accounts.offer;
+        require!(!offer.accepted, ErrorCode::OfferAlreadyAccepted);
+
+        let now = Clock::get()?.unix_timestamp;
+        let open = now < offer.expiry && now < market.end_time && !market.resolved && !market.emergency;
+        require!(ctx.accounts.caller.key() == offer.maker || !open, ErrorCode::Unauthorized);
+
+        let refund = ctx.accounts.offer_vault.amount;
+        close_offer_vault(
+            offer,
+            &ctx.accounts.offer_vault,
+            &[(ctx.accounts.maker_token_account.to_account_info(), refund)],
+            &ctx.accounts.maker,
+            &ctx.accounts.token_program
+        )?;
+
+        emit!(OfferCancelled {
+            market: market.key(),
+            offer: offer.key(),
+            maker: offer.maker,
+            refund
+        });
+        Ok(())
+    }
+
+    /// Pay out an accepted offer once its market is final (permissionless). The side that called
+    /// the winning outcome takes both stakes; a voided or emergency market refunds each stake.
+    pub fn settle_offer(ctx: Context<SettleOffer>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let offer = &ctx.accounts.offer;
+        require!(offer.accepted, ErrorCode::OfferNotAccepted);
+        require!(market.resolved || market.emergency, ErrorCode::MarketNotResolved);
+
+        let pot = ctx.accounts.offer_vault.amount;
+        let (winner, maker_payout) = if market.voided || market.emergency {
+            (None, pot.checked_sub(offer.taker_stake).ok_or(ErrorCode::InsufficientEscrow)?)
+        } else if market.winning_outcome == Some(offer.outcome) {
+            (Some(offer.maker), pot)
+        } else {
+            (offer.taker, 0)
+        };
+        let taker_payout = pot - maker_payout;
+
+        close_offer_vault(
+            offer,
+            &ctx.accounts.offer_vault,
+            &[
+                (ctx.accounts.maker_token_account.to_account_info(), maker_payout),
+                (ctx.accounts.taker_token_account.to_account_info(), taker_payout),
+            ],
+            &ctx.accounts.maker,
+            &ctx.accounts.token_program
+        )?;
+
+        emit!(OfferSettled {
+            market: market.key(),
+            offer: offer.key(),
+            winner,
+            maker_payout,
+            taker_payout
+        });
+        Ok(())
+    }
+
+    /// Open the vault that underwrites parlays in `token_mint` (authority only).
+    pub fn init_parlay_vault(ctx: Context<InitParlayVault>) -> Result<()> {
+        let parlay_vault = &mut ctx.accounts.parlay_vault;
+        parlay_vault.bump = ctx.bumps.parlay_vault;
+        parlay_vault.token_mint = ctx.accounts.token_mint.key();
+        parlay_vault.parlay_count = 0;
+        parlay_vault.liabilities = 0;
+        Ok(())
+    }
+
+    /// Add underwriting capital to the parlay vault.
+    pub fn fund_parlay_vault(ctx: Context<FundParlayVault>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.funder_token_account.to_account_info(),
+                    to: ctx.accounts.reserve.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            ),
+            amount
+        )?;
+
+        emit!(ParlayVaultFunded {
+            vault: ctx.accounts.parlay_vault.key(),
+            funder: ctx.accounts.funder.key(),
+            amount
+        });
+        Ok(())
+    }
+
+    /// Take capital out of the parlay vault (authority only), up to what open parlays do not need.
+    pub fn withdraw_parlay_vault(ctx: Context<WithdrawParlayVault>, amount: u64) -> Result<()> {
+        let parlay_vault = &ctx.accounts.parlay_vault;
+        let free = ctx.accounts.reserve.amount.saturating_sub(parlay_vault.liabilities);
+        require!(amount > 0 && amount <= free, ErrorCode::InsufficientUnderwriting);
+
+        let mint_key = parlay_vault.token_mint;
+        let signer_seeds: &[&[&[u8]]] = &[&[b"parlay_vault", mint_key.as_ref(), &[parlay_vault.bump]]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.reserve.to_account_info(),
+                    to: ctx.accounts.recipient_token_account.to_account_info(),
+                    authority: parlay_vault.to_account_info(),
+                },
+                signer_seeds
+            ),
+            amount
+        )?;
+
+        emit!(ParlayVaultWithdrawn {
+            vault: parlay_vault.key(),
+            amount
+        });
+        Ok(())
+    }
+
+    /// Bet `stake` on `outcomes[i]` of every market passed as writable remaining accounts, in
+    /// order. Each leg is priced by `Market::leg_odds` on its pools and price snapshot, and the
+    /// vault reserves the full payout. Fails with `SlippageExceeded` below `min_payout`. Open legs
+    /// are counted on their markets, which cannot be made conditional until the parlay settles.
+    pub fn create_parlay<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreateParlay<'info>>,
+        stake: u64,
+        outcomes: Vec<u8>,
+        min_payout: u64,
+    ) -> Result<()> {
+        require!(stake > 0, ErrorCode::InvalidAmount);
+        require!((MIN_PARLAY_LEGS..=MAX_PARLAY_LEGS).contains(&outcomes.len()), ErrorCode::InvalidParlay);
+        require!(ctx.remaining_accounts.len() == outcomes.len(), ErrorCode::InvalidParlay);
+        require!(!ctx.accounts.protocol.paused, ErrorCode::ProtocolPaused);
+
+        let now = Clock::get()?.unix_timestamp;
+        let fee_bps = ctx.accounts.protocol.amm_fee;
+        let mut legs = Vec::with_capacity(outcomes.len());
+        for (info, outcome) in ctx.remaining_accounts.iter().zip(outcomes) {
+            let mut market = Account::<Market>::try_from(info)?;
+            require!(legs.iter().all(|leg: &ParlayLeg| leg.market != market.key()), ErrorCode::InvalidParlay);
+            require_keys_eq!(market.token_mint, ctx.accounts.parlay_vault.token_mint, ErrorCode::InvalidMint);
+            require!(
+                market.market_type == MarketType::Categorical && market.outcomes.len() == 2,
+                ErrorCode::InvalidMarketType
+            );
+            require!(market.pricing == PricingMode::ConstantProduct, ErrorCode::UnsupportedPricingMode);
+            require!(market.parent.is_none(), ErrorCode::ConditionalMarket);
+            require!((outcome as usize) < market.outcomes.len(), ErrorCode::InvalidOutcome);
+            require!(!market.paused, ErrorCode::MarketPaused);
+            require!(!market.resolved, ErrorCode::MarketResolved);
+            require!(!market.emergency, ErrorCode::EmergencyMode);
+            require!(now < market.end_time, ErrorCode::MarketEnded);
+
+            let odds = market.leg_odds(outcome as usize, fee_bps)?;
+            // Dropping a void leg must never raise the payout above what the vault reserved
+            require!(odds as u128 >= PRECISION, ErrorCode::InvalidParlay);
+            legs.push(ParlayLeg {
+                market: market.key(),
+                outcome,
+                odds,
+            });
+
+            market.parlay_legs = market.parlay_legs.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+            market.exit(&crate::ID)?;
+        }
+
+        let parlay_vault = &mut ctx.accounts.parlay_vault;
+        let parlay = &mut ctx.accounts.parlay;
+        parlay.id = parlay_vault.parlay_count;
+        parlay.bump = ctx.bumps.parlay;
+        parlay.vault = parlay_vault.key();
+        parlay.user = ctx.accounts.user.key();
+        parlay.stake = stake;
+        parlay.legs = legs;
+        parlay.max_payout = parlay.payout(|_| true)?;
+        require!(parlay.max_payout >= min_payout, ErrorCode::SlippageExceeded);
+
+        // The stake joins the reserve, which must cover every open parlay winning
+        let liabilities = parlay_vault.liabilities.checked_add(parlay.max_payout).ok_or(ErrorCode::AmountOverflow)?;
+        let reserve = ctx.accounts.reserve.amount.checked_add(stake).ok_or(ErrorCode::AmountOverflow)?;
+        require!(reserve >= liabilities, ErrorCode::InsufficientUnderwriting);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.reserve.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            stake
+        )?;
+
+        parlay_vault.liabilities = liabilities;
+        parlay_vault.parlay_count = parlay_vault.parlay_count.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
+
+        emit!(ParlayCreated {
+            vault: parlay_vault.key(),
+            parlay: parlay.key(),
+            user: parlay.user,
+            stake,
+            max_payout: parlay.max_payout,
+            legs: parlay.legs.len() as u8
+        });
+        Ok(())
+    }
+
+    /// Pay out a parlay once every leg's market is final (permissionless). Pass the leg markets as
+    /// writable remaining accounts, in order. Voided and emergency legs are dropped from the odds; any
+    /// other losing leg loses the parlay. The parlay is closed and its reservation released.
+    pub fn settle_parlay<'info>(ctx: Context<'_, '_, 'info, 'info, SettleParlay<'info>>) -> Result<()> {
+        let parlay = &ctx.accounts.parlay;
+        require!(ctx.remaining_accounts.len() == parlay.legs.len(), ErrorCode::InvalidParlay);
+
+        let mut live = Vec::with_capacity(parlay.legs.len());
+        let mut lost = false;
+        for (info, leg) in ctx.remaining_accounts.iter().zip(&parlay.legs) {
+            require_keys_eq!(info.key(), leg.market, ErrorCode::InvalidParlay);
+            let mut market = Account::<Market>::try_from(info)?;
+
+            let void = market.voided || market.emergency;
+            if !void {
+                require!(market.resolved, ErrorCode::MarketNotResolved);
+                lost |= market.winning_outcome != Some(leg.outcome);
+            }
+            live.push(!void);
+
+            market.parlay_legs = market.parlay_legs.checked_sub(1).ok_or(ErrorCode::AmountOverflow)?;
+            market.exit(&crate::ID)?;
+        }
+
+        let payout = if lost { 0 } else { parlay.payout(|i| live[i])? };
+        let void_legs = live.iter().filter(|live| !**live).count() as u8;
+
+        let parlay_vault = &mut ctx.accounts.parlay_vault;
+        parlay_vault.liabilities = parlay_vault.liabilities.checked_sub(parlay.max_payout).ok_or(ErrorCode::AmountOverflow)?;
+
+        if payout > 0 {
+            let mint_key = parlay_vault.token_mint;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"parlay_vault", mint_key.as_ref(), &[parlay_vault.bump]]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Transfer {
+                        from: ctx.accounts.reserve.to_account_info(),
+                        to: ctx.accounts.user_token_account.to_account_info(),
+                        authority: parlay_vault.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                payout
+            )?;
+        }
+
+        emit!(ParlaySettled {
+            vault: parlay_vault.key(),
+            parlay: parlay.key(),
+            user: parlay.user,
+            payout,
+            void_legs
+        });
+        Ok(())
+    }
+
+    /// Make a market conditional on `parent_outcome` of another categorical market (creator only,
+    /// before any bet). Winnings are only paid if the parent resolves that way; otherwise
+    /// `void_conditional_market` voids the market and every position is refunded.
+    pub fn link_parent_market(ctx: Context<LinkParentMarket>, parent_outcome: u8) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let parent = &ctx.accounts.parent_market;
+        require!(ctx.accounts.creator.key() == market.creator, ErrorCode::Unauthorized);
+        require!(market.parent.is_none(), ErrorCode::ConditionalMarket);
+        require_keys_neq!(parent.key(), market.key(), ErrorCode::InvalidOutcome);
+        require!(!market.resolved, ErrorCode::MarketResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        // Winning positions and the creator's seed are then the only ways collateral leaves a
+        // resolved market, both checked against the condition
+        require!(market.pricing == PricingMode::ConstantProduct, ErrorCode::UnsupportedPricingMode);
+        require!(market.outcome_mint_count == 0, ErrorCode::OutcomeTokenMarket);
+        require!(market.lp_shares == market.seed_shares, ErrorCode::ConditionalMarket);
+        require!(market.position_count == 0 && market.offer_count == 0 && market.parlay_legs == 0, ErrorCode::MarketHasBets);
+
+        require!(parent.market_type == MarketType::Categorical, ErrorCode::InvalidMarketType);
+        require!((parent_outcome as usize) < parent.outcomes.len(), ErrorCode::InvalidOutcome);
+        require!(!parent.resolved, ErrorCode::MarketResolved);
+        require!(!parent.emergency, ErrorCode::EmergencyMode);
+
+        market.parent = Some(ParentCondition {
+            market: parent.key(),
+            outcome: parent_outcome,
+        });
+
+        emit!(ParentMarketLinked {
+            market: market.key(),
+            parent_market: parent.key(),
+            parent_outcome
+        });
+        Ok(())
+    }
+
+    /// Void a conditional market whose parent resolved to another outcome, was voided or went into
+    /// emergency mode (permissionless). Positions then take their stake back with `claim_refund`.
+    pub fn void_conditional_market(ctx: Context<VoidConditionalMarket>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let parent = &ctx.accounts.parent_market;
+        let condition = market.parent.ok_or(ErrorCode::ConditionNotMet)?;
+        require_keys_eq!(parent.key(), condition.market, ErrorCode::ConditionNotMet);
+        require!(parent.resolved || parent.emergency, ErrorCode::MarketNotResolved);
+        require!(
+            parent.voided || parent.emergency || parent.winning_outcome != Some(condition.outcome),
+            ErrorCode::ConditionMet
+        );
+
+        // The market may already have resolved on its own: nobody could claim, so void it anyway
+        require!(!market.voided, ErrorCode::AlreadyResolved);
+        require!(!market.emergency, ErrorCode::EmergencyMode);
+        require!(market.resolved || market.proposal.is_none(), ErrorCode::ResolutionPending);
+
+        market.resolved = true;
+        market.voided = true;
+        market.winning_outcome = None;
+        market.resolved_value = None;
+
+        emit!(ConditionalMarketVoided {
+            market: market.key(),
+            parent_market: parent.key(),
+            parent_outcome: parent.winning_outcome
+        });
+        Ok(())
+    }
+}
+
+/// Move the proposer bond into the bond vault and open the dispute window.
+fn propose_resolution(accounts: &mut ResolveMarket, outcome: Option<u8>, value: Option<i64>) -> Result<()> {
+    let bond = accounts.protocol.dispute_bond;
+    if bond > 0 {
+        token::transfer(
+            CpiContext::new(
+                accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: accounts.creator_token_account.to_account_info(),
+                    to: accounts.bond_vault.to_account_info(),
+                    authority: accounts.creator.to_account_info(),
+                },
+            ),
+            bond
+        )?;
+    }
+
+    let dispute_deadline = Clock::get()?.unix_timestamp
+        .checked_add(accounts.protocol.dispute_window).ok_or(ErrorCode::AmountOverflow)?;
+
+    let market = &mut accounts.market;
+    market.proposal = Some(ResolutionProposal {
+        proposer: accounts.creator.key(),
+        outcome,
+        value,
+        bond,
+        dispute_deadline,
+        disputer: None,
+    });
+
+    emit!(ResolutionProposed {
+        market: market.key(),
+        proposer: accounts.creator.key(),
+        outcome,
+        value,
+        bond,
+        dispute_deadline
+    });
+    Ok(())
+}
+
+/// Mark the market as resolved so `claim_winnings` can pay out.
+fn apply_resolution(market: &mut Account<Market>, outcome: Option<u8>, value: Option<i64>) -> Result<()> {
+    require!(market.pending_fills == 0, ErrorCode::UnsettledFills);
+    market.resolved = true;
+    market.winning_outcome = outcome;
+    market.resolved_value = value;
+
+    match market.market_type {
+        MarketType::Categorical => {
+            let winner = outcome.ok_or(ErrorCode::InvalidOutcome)?;
+            emit!(MarketResolved {
+                market: market.key(),
+                winner
+            });
+        }
+        MarketType::Scalar { .. } => {
+            let value = value.ok_or(ErrorCode::InvalidMarketType)?;
+            emit!(ScalarMarketResolved {
+                market: market.key(),
+                value
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check that `accounts` holds `[outcome_mint, user_outcome_account]` for every outcome of
+/// the market, in outcome order, and return the pairs.
+fn outcome_set_accounts<'info>(
+    market: &Account<'info, Market>,
+    user: &Pubkey,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<(&'info AccountInfo<'info>, &'info AccountInfo<'info>)>> {
+    require!(accounts.len() == market.outcomes.len() * 2, ErrorCode::InvalidOutcomeAccounts);
+
+    let market_key = market.key();
+    accounts
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let (expected_mint, _) = Pubkey::find_program_address(
+                &[b"outcome_mint", market_key.as_ref(), &[i as u8]],
+                &crate::ID
+            );
+            require_keys_eq!(pair[0].key(), expected_mint, ErrorCode::InvalidOutcomeAccounts);
+
+            let holder = Account::<TokenAccount>::try_from(&pair[1])?;
+            require_keys_eq!(holder.mint, expected_mint, ErrorCode::InvalidOutcomeAccounts);
+            require_keys_eq!(holder.owner, *user, ErrorCode::InvalidOutcomeAccounts);
+
+            Ok((&pair[0], &pair[1]))
+        })
+        .collect()
+}
+
+/// An outcome's mint, the user's account of it and the outcome.
+type OutcomeTokens<'a, 'info> = (&'a Account<'info, Mint>, &'a Account<'info, TokenAccount>, usize);
+
+/// The outcome tokens standing in for a position in an outcome token market. `None` for markets
+/// that keep positions.
+fn outcome_token_accounts<'a, 'info>(
+    market: &Account<'info, Market>,
+    outcome_mint: &'a Option<Account<'info, Mint>>,
+    user_outcome_account: &'a Option<Account<'info, TokenAccount>>,
+) -> Result<Option<OutcomeTokens<'a, 'info>>> {
+    if !market.has_outcome_tokens() {
+        return Ok(None);
+    }
+    let outcome_mint = outcome_mint.as_ref().ok_or(ErrorCode::MissingOutcomeAccounts)?;
+    let user_outcome_account = user_outcome_account.as_ref().ok_or(ErrorCode::MissingOutcomeAccounts)?;
+
+    let market_key = market.key();
+    let outcome = (0..market.outcomes.len())
+        .find(|&i| {
+            let (expected_mint, _) = Pubkey::find_program_address(
+                &[b"outcome_mint", market_key.as_ref(), &[i as u8]],
+                &crate::ID
+            );
+            expected_mint == outcome_mint.key()
+        })
+        .ok_or(ErrorCode::InvalidOutcomeAccounts)?;
+    Ok(Some((outcome_mint, user_outcome_account, outcome)))
+}
+
+/// Burn `amount` of the user's tokens of an outcome.
+fn burn_outcome_tokens<'info>(
+    outcome_mint: &Account<'info, Mint>,
+    user_outcome_account: &Account<'info, TokenAccount>,
+    user: &Signer<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    token::burn(
+        CpiContext::new(
             token_program.to_account_info(),
-            token::Transfer {
-                from: escrow.to_account_info(),
-                to: authority_account.to_account_info(),
-                authority: authority.clone(),
+            token::Burn {
+                mint: outcome_mint.to_account_info(),
+                from: user_outcome_account.to_account_info(),
+                authority: user.to_account_info(),
             },
+        ),
+        amount
+    )
+}
+
+/// Create and initialize the `["outcome_mint", market, outcome]` mint PDA, with the market as
+/// mint authority. Lamports already sent to the address only reduce what the payer tops up.
+fn create_outcome_mint<'info>(
+    market: &Pubkey,
+    outcome: u8,
+    mint: &'info AccountInfo<'info>,
+    payer: &Signer<'info>,
+    decimals: u8,
+    token_program: &Program<'info, Token>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let (expected_mint, bump) = Pubkey::find_program_address(
+        &[b"outcome_mint", market.as_ref(), &[outcome]],
+        &crate::ID
+    );
+    require_keys_eq!(mint.key(), expected_mint, ErrorCode::InvalidOutcomeAccounts);
+    require!(mint.data_is_empty() && mint.owner == &System::id(), ErrorCode::InvalidOutcomeAccounts);
+
+    let signer_seeds: &[&[&[u8]]] = &[&[b"outcome_mint", market.as_ref(), &[outcome], &[bump]]];
+    let rent = Rent::get()?.minimum_balance(token::spl_token::state::Mint::LEN);
+    let top_up = rent.saturating_sub(mint.lamports());
+    if top_up > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer { from: payer.to_account_info(), to: mint.clone() },
+            ),
+            top_up
+        )?;
+    }
+    system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Allocate { account_to_allocate: mint.clone() },
+            signer_seeds
+        ),
+        token::spl_token::state::Mint::LEN as u64
+    )?;
+    system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Assign { account_to_assign: mint.clone() },
             signer_seeds
         ),
-        half
+        &token::ID
     )?;
 
+    token::initialize_mint2(
+        CpiContext::new(token_program.to_account_info(), token::InitializeMint2 { mint: mint.clone() }),
+        decimals,
+        market,
+        None
+    )
+}
+
+/// Bets on a conditional market only count once the parent resolved to its outcome.
+fn require_condition(condition: ParentCondition, parent: Option<&Account<Market>>) -> Result<()> {
+    let parent = parent.ok_or(ErrorCode::ConditionNotMet)?;
+    require_keys_eq!(parent.key(), condition.market, ErrorCode::ConditionNotMet);
+    require!(
+        parent.resolved && !parent.voided && parent.winning_outcome == Some(condition.outcome),
+        ErrorCode::ConditionNotMet
+    );
+    Ok(())
+}
+
+/// Positions with a Core asset are controlled by the asset holder, others by `Position.user`.
+fn require_position_owner(position: &Position, user: &Pubkey, position_asset: Option<&UncheckedAccount>) -> Result<()> {
+    match position.asset {
+        Some(asset_key) => {
+            let position_asset = position_asset.ok_or(ErrorCode::MissingPositionAsset)?;
+            require_keys_eq!(position_asset.key(), asset_key, ErrorCode::InvalidPositionAsset);
+            require_keys_eq!(asset::load_asset_owner(position_asset)?, *user, ErrorCode::PositionOwnerMismatch);
+        }
+        None => require!(position.user == *user, ErrorCode::PositionOwnerMismatch),
+    }
+    Ok(())
+}
+
+/// Keep the attributes of a position asset in line with the position stake.
+fn sync_position_asset<'info>(
+    market: &Account<'info, Market>,
+    position: &Position,
+    position_asset: &Option<UncheckedAccount<'info>>,
+    mpl_core_program: &Option<UncheckedAccount<'info>>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if position.asset.is_none() {
+        return Ok(());
+    }
+    let position_asset = position_asset.as_ref().ok_or(ErrorCode::MissingPositionAsset)?;
+    let mpl_core_program = mpl_core_program.as_ref().ok_or(ErrorCode::MissingPositionAsset)?;
+
+    asset::update_position_attributes(
+        mpl_core_program,
+        position_asset,
+        payer,
+        &market.to_account_info(),
+        system_program,
+        signer_seeds,
+        &asset::position_attributes(&market.key(), &market.outcomes[position.outcome as usize], position.amount)
+    )
+}
+
+/// Burn the asset of a settled position so it cannot be resold.
+fn burn_position_asset<'info>(
+    position: &Position,
+    position_asset: &Option<UncheckedAccount<'info>>,
+    mpl_core_program: &Option<UncheckedAccount<'info>>,
+    owner: &Signer<'info>,
+) -> Result<()> {
+    if position.asset.is_none() {
+        return Ok(());
+    }
+    let position_asset = position_asset.as_ref().ok_or(ErrorCode::MissingPositionAsset)?;
+    let mpl_core_program = mpl_core_program.as_ref().ok_or(ErrorCode::MissingPositionAsset)?;
+
+    asset::burn_position_asset(mpl_core_program, position_asset, owner)
+}
+
+fn validate_fees(protocol_fee_bps: u16, cancel_fee_bps: u16, amm_fee: u16) -> Result<()> {
+    require!(protocol_fee_bps <= MAX_PROTOCOL_FEE_BPS, ErrorCode::FeeTooHigh);
+    require!(cancel_fee_bps <= MAX_CANCEL_FEE_BPS, ErrorCode::FeeTooHigh);
+    require!(amm_fee <= MAX_AMM_FEE_BPS, ErrorCode::FeeTooHigh);
+
+    // Withdraw/cancel charge both fees on the same gross payout
+    let exit_fee = (amm_fee as u32).checked_add(cancel_fee_bps as u32).ok_or(ErrorCode::AmountOverflow)?;
+    require!(exit_fee < BPS_DENOMINATOR as u32, ErrorCode::FeeTooHigh);
+    Ok(())
+}
+
+/// Split `total_fee` between the authority and the dev recipient according to `dev_fee_share_bps`.
+/// A recipient whose share rounds to zero does not need to provide its token account.
+#[allow(clippy::too_many_arguments)]
+fn distribute_fees<'info>(
+    escrow: &Account<'info, TokenAccount>,
+    authority_account: Option<&Account<'info, TokenAccount>>,
+    dev_account: Option<&Account<'info, TokenAccount>>,
+    authority: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    signer_seeds: &[&[&[u8]]],
+    total_fee: u64,
+    dev_fee_share_bps: u16,
+) -> Result<()> {
+    if total_fee == 0 {
+        return Ok(());
+    }
+
+    let dev_fee = (total_fee as u128)
+        .checked_mul(dev_fee_share_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+        .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+    let authority_fee = total_fee.checked_sub(dev_fee).ok_or(ErrorCode::AmountOverflow)?;
+
+    for (recipient, amount) in [(authority_account, authority_fee), (dev_account, dev_fee)] {
+        if amount == 0 {
+            continue;
+        }
+        let recipient = recipient.ok_or(ErrorCode::MissingFeeAccount)?;
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::Transfer {
+                    from: escrow.to_account_info(),
+                    to: recipient.to_account_info(),
+                    authority: authority.clone(),
+                },
+                signer_seeds
+            ),
+            amount
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks shared by buy and sell limit orders.
+fn validate_order(market: &Market, protocol: &Protocol, limit_price_bps: u16, expiry: i64) -> Result<()> {
+    require!(market.outcome_mint_count == 0, ErrorCode::OutcomeTokenMarket);
+    require!(market.pricing != PricingMode::OrderBook, ErrorCode::UnsupportedPricingMode);
+    require!(!market.resolved, ErrorCode::MarketResolved);
+    require!(!market.emergency, ErrorCode::EmergencyMode);
+    require!(!protocol.paused, ErrorCode::ProtocolPaused);
+    require!(!market.paused, ErrorCode::MarketPaused);
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(now < market.end_time, ErrorCode::MarketEnded);
+    require!(expiry > now, ErrorCode::OrderExpired);
+    require!(limit_price_bps > 0 && limit_price_bps <= BPS_DENOMINATOR, ErrorCode::InvalidOrder);
+    Ok(())
+}
+
+/// Cross a buy and a sell order at `price_bps`: as many shares as the sell order has left and the
+/// buy order can pay for move to the buyer's position, and their cost moves between the vaults.
+/// Returns (shares, collateral).
+#[allow(clippy::too_many_arguments)]
+fn cross_orders<'info>(
+    market: &mut Market,
+    buy: &mut Account<'info, Order>,
+    buy_vault: &Account<'info, TokenAccount>,
+    buy_position: &mut Account<'info, Position>,
+    sell: &mut Account<'info, Order>,
+    sell_vault: &Account<'info, TokenAccount>,
+    sell_position: &mut Account<'info, Position>,
+    price_bps: u16,
+    token_program: &Program<'info, Token>,
+) -> Result<(u64, u64)> {
+    require!(buy.limit_price_bps >= sell.limit_price_bps, ErrorCode::OrderNotFillable);
+
+    let affordable = (buy.remaining as u128)
+        .checked_mul(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)?
+        .checked_div(price_bps as u128).ok_or(ErrorCode::AmountOverflow)?;
+    let shares = affordable.min(sell.remaining as u128) as u64;
+    require!(shares > 0, ErrorCode::OrderNotFillable);
+    // Rounded up in the seller's favour, still within the buyer's remaining collateral
+    let collateral = (shares as u128)
+        .checked_mul(price_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+        .div_ceil(BPS_DENOMINATOR as u128) as u64;
+
+    let cost = sell_position.release(shares)?;
+    sell_position.locked = sell_position.locked.checked_sub(shares).ok_or(ErrorCode::AmountOverflow)?;
+    buy_position.amount = buy_position.amount.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+    buy_position.cost = buy_position.cost.checked_add(collateral).ok_or(ErrorCode::AmountOverflow)?;
+
+    // The shares change hands at the crossing price, which becomes their cost
+    let idx = buy.outcome as usize;
+    market.release_shares(idx, shares, cost)?;
+    market.hold_shares(idx, shares, collateral)?;
+
+    buy.remaining = buy.remaining.checked_sub(collateral).ok_or(ErrorCode::AmountOverflow)?;
+    buy.filled = buy.filled.checked_add(shares).ok_or(ErrorCode::AmountOverflow)?;
+    sell.remaining = sell.remaining.checked_sub(shares).ok_or(ErrorCode::AmountOverflow)?;
+    sell.filled = sell.filled.checked_add(collateral).ok_or(ErrorCode::AmountOverflow)?;
+
+    let buy_id = buy.id.to_le_bytes();
+    let buy_bump = [buy.bump];
+    let buy_seeds = buy.signer_seeds(&buy_id, &buy_bump);
     token::transfer(
         CpiContext::new_with_signer(
             token_program.to_account_info(),
             token::Transfer {
-                from: escrow.to_account_info(),
-                to: dev_account.to_account_info(),
-                authority: authority.clone(),
+                from: buy_vault.to_account_info(),
+                to: sell_vault.to_account_info(),
+                authority: buy.to_account_info(),
             },
-            signer_seeds
+            &[&buy_seeds[..]]
         ),
-        remainder
+        collateral
     )?;
 
-    Ok(())
-}
\ No newline at end of file
+    Ok((shares, collateral))
+}
+
+/// Pay out an offer vault, signed by the offer, and close it to the maker.
+fn close_offer_vault<'info>(
+    offer: &Account<'info, Offer>,
+    offer_vault: &Account<'info, TokenAccount>,
+    payouts: &[(AccountInfo<'info>, u64)],
+    maker: &UncheckedAccount<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let offer_id = offer.id.to_le_bytes();
+    let offer_bump = [offer.bump];
+    let offer_seeds = offer.signer_seeds(&offer_id, &offer_bump);
+    let signer_seeds: &[&[&[u8]]] = &[&offer_seeds[..]];
+
+    for (to, amount) in payouts.iter().filter(|(_, amount)| *amount > 0) {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                token::Transfer {
+                    from: offer_vault.to_account_info(),
+                    to: to.clone(),
+                    authority: offer.to_account_info(),
+                },
+                signer_seeds
+            ),
+            *amount
+        )?;
+    }
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        token::CloseAccount {
+            account: offer_vault.to_account_info(),
+            destination: maker.to_account_info(),
+            authority: offer.to_account_info(),
+        },
+        signer_seeds
+    ))
+}
@@ -0,0 +1,233 @@
+use anchor_lang::prelude::*;
+use crate::error::ErrorCode;
+use crate::PRECISION;
+
+/// ln(2) on `PRECISION`
+const LN_2: u128 = 693_147_181;
+/// e^-z rounds to zero on `PRECISION` past z = 21
+const MAX_EXP_NEG_ARG: u128 = 21 * PRECISION;
+
+/// e^-z for a fixed point z >= 0.
+pub fn exp_neg(z: u128) -> u128 {
+    if z >= MAX_EXP_NEG_ARG {
+        return 0;
+    }
+
+    // e^-z = 2^-k * e^-r with r in [0, ln 2)
+    let k = z / LN_2;
+    let r = z % LN_2;
+
+    // Taylor series of e^r, every term positive
+    let mut sum = PRECISION;
+    let mut term = PRECISION;
+    let mut n = 1u128;
+    while term > 0 {
+        term = term * r / PRECISION / n;
+        sum += term;
+        n += 1;
+    }
+
+    // e^-r = 1 / e^r, then halve k times
+    (PRECISION * PRECISION / sum) >> k
+}
+
+/// ln(x) for a fixed point x > 0.
+pub fn ln(x: u128) -> Result<i128> {
+    require!(x > 0, ErrorCode::AmountOverflow);
+
+    // x = 2^k * y with y in [1, 2)
+    let mut k = 0i128;
+    let mut y = x;
+    while y >= 2 * PRECISION {
+        y >>= 1;
+        k += 1;
+    }
+    while y < PRECISION {
+        y <<= 1;
+        k -= 1;
+    }
+
+    // ln(y) = 2 * atanh(s) = 2 * (s + s^3/3 + s^5/5 + ...), s = (y - 1) / (y + 1) in [0, 1/3)
+    let s = (y - PRECISION) * PRECISION / (y + PRECISION);
+    let s2 = s * s / PRECISION;
+    let mut sum = 0u128;
+    let mut power = s;
+    let mut n = 1u128;
+    while power > 0 {
+        sum += power / n;
+        power = power * s2 / PRECISION;
+        n += 2;
+    }
+
+    Ok(k * LN_2 as i128 + 2 * sum as i128)
+}
+
+/// `e^((q_j - max q) / b)` for every outcome, the largest term being exactly 1, and `max q`.
+/// Shifting every exponent by the largest share count keeps the sums in range (log-sum-exp).
+fn normalized_terms(shares: &[u64], b: u64) -> Result<(Vec<u128>, u64)> {
+    require!(b > 0, ErrorCode::InvalidLiquidityParameter);
+    let max = shares.iter().copied().max().ok_or(ErrorCode::InvalidOutcome)?;
+    let terms = shares
+        .iter()
+        .map(|q| exp_neg((max - q) as u128 * PRECISION / b as u128))
+        .collect();
+    Ok((terms, max))
+}
+
+/// The creator's worst case loss, `b * ln(n)`, rounded up. Funded up front as the market subsidy.
+pub fn max_loss(b: u64, outcome_count: usize) -> Result<u64> {
+    require!(b > 0, ErrorCode::InvalidLiquidityParameter);
+    let ln_n = ln(outcome_count as u128 * PRECISION)? as u128;
+    (b as u128)
+        .checked_mul(ln_n).ok_or(ErrorCode::AmountOverflow)?
+        .div_ceil(PRECISION)
+        .try_into().map_err(|_| ErrorCode::AmountOverflow.into())
+}
+
+/// Shares of outcome `idx` bought by `amount` of collateral, i.e. the x solving
+/// `C(q + x * e_idx) - C(q) = amount` with cost function `C(q) = b * ln(sum(e^(q_j / b)))`:
+///
+/// `x = b * (y + ln(S * (1 - e^-y) + e_idx * e^-y)) - ln(e_idx) * b`, y = amount / b,
+/// where S and e_idx are the normalized terms so ln(e_idx) = (q_idx - max q) / b.
+pub fn buy_shares(shares: &[u64], b: u64, idx: usize, amount: u64) -> Result<u64> {
+    let (terms, max) = normalized_terms(shares, b)?;
+    let sum: u128 = terms.iter().sum();
+
+    let y = (amount as u128).checked_mul(PRECISION).ok_or(ErrorCode::AmountOverflow)? / b as u128;
+    let decay = exp_neg(y);
+    let inner = sum * (PRECISION - decay) / PRECISION + terms[idx] * decay / PRECISION;
+
+    let x = (y as i128).checked_add(ln(inner)?).ok_or(ErrorCode::AmountOverflow)?;
+    let bought = (b as i128)
+        .checked_mul(x).ok_or(ErrorCode::AmountOverflow)?
+        .div_euclid(PRECISION as i128)
+        .checked_add((max - shares[idx]) as i128).ok_or(ErrorCode::AmountOverflow)?;
+
+    u64::try_from(bought.max(0)).map_err(|_| ErrorCode::AmountOverflow.into())
+}
+
+/// Collateral paid for selling `amount` shares of outcome `idx`, `C(q) - C(q - amount * e_idx)`:
+///
+/// `b * (ln(S) - ln(S - e_idx * (1 - e^-z)))`, z = amount / b.
+pub fn sell_payout(shares: &[u64], b: u64, idx: usize, amount: u64) -> Result<u64> {
+    require!(amount <= shares[idx], ErrorCode::InsufficientLiquidity);
+    let (terms, _) = normalized_terms(shares, b)?;
+    let sum: u128 = terms.iter().sum();
+
+    let z = (amount as u128).checked_mul(PRECISION).ok_or(ErrorCode::AmountOverflow)? / b as u128;
+    let rest = sum - terms[idx] + terms[idx] * exp_neg(z) / PRECISION;
+
+    let payout = (b as i128)
+        .checked_mul(ln(sum)? - ln(rest)?).ok_or(ErrorCode::AmountOverflow)?
+        .div_euclid(PRECISION as i128);
+
+    u64::try_from(payout.max(0)).map_err(|_| ErrorCode::AmountOverflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P: f64 = PRECISION as f64;
+
+    fn cost(shares: &[f64], b: f64) -> f64 {
+        b * shares.iter().map(|q| (q / b).exp()).sum::<f64>().ln()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn exp_neg_matches_f64() {
+        for z in [0.0, 1e-9, 0.1, 0.5, 0.69, 1.0, 2.5, 7.3, 15.0, 20.9] {
+            let actual = exp_neg((z * P) as u128) as f64 / P;
+            assert_close(actual, (-z).exp(), 3e-9);
+        }
+        assert_eq!(exp_neg(25 * PRECISION), 0);
+    }
+
+    #[test]
+    fn ln_matches_f64() {
+        for x in [1e-6, 0.01, 0.5, 1.0, 1.5, 2.0, std::f64::consts::E, 16.0, 1234.5] {
+            let actual = ln((x * P) as u128).unwrap() as f64 / P;
+            // ln(2) is off by < 0.5e-9 and counted once per halving or doubling
+            assert_close(actual, x.ln(), 2e-8);
+        }
+        assert!(ln(0).is_err());
+    }
+
+    #[test]
+    fn max_loss_is_b_ln_n() {
+        assert_eq!(max_loss(1_000_000_000, 2).unwrap(), 693_147_181);
+        assert_close(max_loss(50_000_000, 16).unwrap() as f64, 50_000_000.0 * 16f64.ln(), 1.0);
+        assert!(max_loss(0, 2).is_err());
+    }
+
+    #[test]
+    fn buy_shares_matches_f64_reference() {
+        let b = 100_000_000u64;
+        let cases: [(&[u64], usize, u64); 5] = [
+            (&[0, 0], 0, 10_000_000),
+            (&[50_000_000, 0], 0, 10_000_000),
+            (&[50_000_000, 0], 1, 10_000_000),
+            (&[0, 30_000_000, 90_000_000], 0, 250_000_000),
+            (&[2_000_000_000, 0], 1, 5_000_000),
+        ];
+
+        for (shares, idx, amount) in cases {
+            let bought = buy_shares(shares, b, idx, amount).unwrap();
+
+            // Closed form in f64: x = b * ln(1 + S * (e^(amount / b) - 1) / e_idx)
+            let q: Vec<f64> = shares.iter().map(|s| *s as f64).collect();
+            let bf = b as f64;
+            let sum: f64 = q.iter().map(|q| (q / bf).exp()).sum();
+            let expected = bf * (1.0 + sum * ((amount as f64 / bf).exp() - 1.0) / (q[idx] / bf).exp()).ln();
+            // Relative error of the fixed point math is ~1e-9
+            let tolerance = 2.0 + expected * 1e-8;
+            assert_close(bought as f64, expected, tolerance);
+
+            // and it costs `amount` under the LMSR cost function
+            let mut after = q.clone();
+            after[idx] += bought as f64;
+            assert_close(cost(&after, bf) - cost(&q, bf), amount as f64, tolerance);
+        }
+    }
+
+    #[test]
+    fn sell_payout_matches_f64_reference() {
+        let b = 100_000_000u64;
+        let cases: [(&[u64], usize, u64); 4] = [
+            (&[10_000_000, 0], 0, 10_000_000),
+            (&[80_000_000, 20_000_000], 0, 30_000_000),
+            (&[80_000_000, 20_000_000], 1, 20_000_000),
+            (&[5_000_000, 300_000_000, 40_000_000], 1, 299_000_000),
+        ];
+
+        for (shares, idx, amount) in cases {
+            let payout = sell_payout(shares, b, idx, amount).unwrap();
+
+            let q: Vec<f64> = shares.iter().map(|s| *s as f64).collect();
+            let mut after = q.clone();
+            after[idx] -= amount as f64;
+            let expected = cost(&q, b as f64) - cost(&after, b as f64);
+            assert_close(payout as f64, expected, 2.0);
+        }
+
+        assert!(sell_payout(&[1, 0], b, 0, 2).is_err());
+    }
+
+    #[test]
+    fn buy_then_sell_returns_the_stake() {
+        let b = 100_000_000u64;
+        let mut shares = vec![40_000_000u64, 10_000_000];
+        let bought = buy_shares(&shares, b, 1, 25_000_000).unwrap();
+        shares[1] += bought;
+
+        let payout = sell_payout(&shares, b, 1, bought).unwrap();
+        assert!((24_999_990..=25_000_000).contains(&payout), "payout {payout}");
+    }
+}
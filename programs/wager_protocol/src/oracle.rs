@@ -0,0 +1,275 @@
+use anchor_lang::prelude::*;
+use crate::error::ErrorCode;
+use crate::structs::{Comparison, PriceThreshold};
+
+/// Pyth Solana receiver program, owner of every `PriceUpdateV2` account
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+/// sha256("account:PriceUpdateV2")[..8]
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+/// Switchboard on-demand programs (mainnet, devnet), owners of `PullFeedAccountData` accounts
+pub const SWITCHBOARD_PROGRAM_IDS: [Pubkey; 2] = [
+    pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv"),
+    pubkey!("Aio4gaXjXzJNVLtzwtNVmSqGKpANtXhybbkhtAC94ji2"),
+];
+
+/// Switchboard values are fixed point with 18 decimals
+pub const SWITCHBOARD_DECIMALS: i32 = 18;
+
+const PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+
+// Offsets in the `PullFeedAccountData` account (repr(C), after the discriminator)
+const PULL_FEED_AUTHORITY_OFFSET: usize = 8 + 2048;
+const PULL_FEED_QUEUE_OFFSET: usize = 8 + 2080;
+const PULL_FEED_LAST_UPDATE_OFFSET: usize = 8 + 2208;
+const PULL_FEED_RESULT_VALUE_OFFSET: usize = 8 + 2256;
+const PULL_FEED_RESULT_SLOT_OFFSET: usize = 8 + 2256 + 104;
+const PULL_FEED_LEN: usize = 8 + 3200;
+
+/// Price read from a Pyth `PriceUpdateV2` account
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PythPrice {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+/// Load a fully verified price from a Pyth receiver account.
+pub fn load_pyth_price(price_account: &AccountInfo) -> Result<PythPrice> {
+    require_keys_eq!(*price_account.owner, PYTH_RECEIVER_PROGRAM_ID, ErrorCode::InvalidOracleAccount);
+    let data = price_account.try_borrow_data()?;
+    parse_pyth_price(&data)
+}
+
+/// Decode a `PriceUpdateV2` account:
+/// discriminator (8) | write_authority (32) | verification_level (1 or 2) | price message | posted_slot (8)
+pub fn parse_pyth_price(data: &[u8]) -> Result<PythPrice> {
+    require!(data.len() >= 8 && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR, ErrorCode::InvalidOracleAccount);
+
+    // VerificationLevel: 0 = Partial { num_signatures: u8 }, 1 = Full
+    let mut offset = 8 + 32;
+    match data.get(offset) {
+        Some(1) => offset += 1,
+        Some(0) => return err!(ErrorCode::OracleNotVerified),
+        _ => return err!(ErrorCode::InvalidOracleAccount),
+    }
+
+    let mut reader = ByteReader { data, offset };
+    let feed_id: [u8; 32] = reader.take()?;
+    let price = i64::from_le_bytes(reader.take()?);
+    let conf = u64::from_le_bytes(reader.take()?);
+    let exponent = i32::from_le_bytes(reader.take()?);
+    let publish_time = i64::from_le_bytes(reader.take()?);
+
+    Ok(PythPrice { feed_id, price, conf, exponent, publish_time })
+}
+
+/// Check that the price was published in `[end_time, end_time + max_staleness]` and that the
+/// confidence interval is within `max_confidence_bps` of the price.
+pub fn validate_pyth_price(price: &PythPrice, end_time: i64, max_staleness: i64, max_confidence_bps: u16) -> Result<()> {
+    require!(price.publish_time >= end_time, ErrorCode::OraclePriceTooEarly);
+    let age = price.publish_time.checked_sub(end_time).ok_or(ErrorCode::AmountOverflow)?;
+    require!(age <= max_staleness, ErrorCode::OraclePriceStale);
+
+    require!(price.price > 0, ErrorCode::InvalidOraclePrice);
+    let max_conf = (price.price as u128)
+        .checked_mul(max_confidence_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+        / 10_000;
+    require!(price.conf as u128 <= max_conf, ErrorCode::OracleConfidenceTooWide);
+    Ok(())
+}
+
+/// Current result of a Switchboard pull feed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SwitchboardResult {
+    pub authority: Pubkey,
+    pub queue: Pubkey,
+    pub value: i128,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// Load the current result of a Switchboard on-demand pull feed.
+pub fn load_switchboard_feed(feed_account: &AccountInfo) -> Result<SwitchboardResult> {
+    require!(SWITCHBOARD_PROGRAM_IDS.contains(feed_account.owner), ErrorCode::InvalidOracleAccount);
+    let data = feed_account.try_borrow_data()?;
+    parse_switchboard_feed(&data)
+}
+
+/// Decode the fields we need from a `PullFeedAccountData` account.
+pub fn parse_switchboard_feed(data: &[u8]) -> Result<SwitchboardResult> {
+    require!(data.len() >= PULL_FEED_LEN && data[..8] == PULL_FEED_DISCRIMINATOR, ErrorCode::InvalidOracleAccount);
+
+    let authority = Pubkey::new_from_array(ByteReader { data, offset: PULL_FEED_AUTHORITY_OFFSET }.take()?);
+    let queue = Pubkey::new_from_array(ByteReader { data, offset: PULL_FEED_QUEUE_OFFSET }.take()?);
+    let timestamp = i64::from_le_bytes(ByteReader { data, offset: PULL_FEED_LAST_UPDATE_OFFSET }.take()?);
+    let value = i128::from_le_bytes(ByteReader { data, offset: PULL_FEED_RESULT_VALUE_OFFSET }.take()?);
+    let slot = u64::from_le_bytes(ByteReader { data, offset: PULL_FEED_RESULT_SLOT_OFFSET }.take()?);
+
+    Ok(SwitchboardResult { authority, queue, value, slot, timestamp })
+}
+
+/// Check that the feed was updated at or after `end_time` and that its result is at most
+/// `max_staleness_slots` old.
+pub fn validate_switchboard_result(result: &SwitchboardResult, end_time: i64, current_slot: u64, max_staleness_slots: u64) -> Result<()> {
+    require!(result.timestamp >= end_time, ErrorCode::OraclePriceTooEarly);
+    require!(result.slot > 0, ErrorCode::InvalidOraclePrice);
+    let age = current_slot.saturating_sub(result.slot);
+    require!(age <= max_staleness_slots, ErrorCode::OraclePriceStale);
+    Ok(())
+}
+
+impl PriceThreshold {
+    /// Whether `price * 10^exponent` satisfies the threshold (both sides scaled to the smaller exponent).
+    pub fn is_met(&self, price: i128, exponent: i32) -> Result<bool> {
+        let min_exponent = exponent.min(self.exponent);
+        let lhs = scale(price, exponent - min_exponent)?;
+        let rhs = scale(self.value as i128, self.exponent - min_exponent)?;
+
+        Ok(match self.comparison {
+            Comparison::Above => lhs > rhs,
+            Comparison::Below => lhs < rhs,
+        })
+    }
+}
+
+fn scale(value: i128, power: i32) -> Result<i128> {
+    let factor = 10i128.checked_pow(power as u32).ok_or(ErrorCode::AmountOverflow)?;
+    Ok(value.checked_mul(factor).ok_or(ErrorCode::AmountOverflow)?)
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.data
+            .get(self.offset..self.offset + N)
+            .ok_or(ErrorCode::InvalidOracleAccount)?;
+        self.offset += N;
+        Ok(bytes.try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_price_update(verification: &[u8], price: i64, conf: u64, exponent: i32, publish_time: i64) -> Vec<u8> {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[7u8; 32]); // write authority
+        data.extend_from_slice(verification);
+        data.extend_from_slice(&[9u8; 32]); // feed id
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&exponent.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&(publish_time - 1).to_le_bytes()); // prev publish time
+        data.extend_from_slice(&price.to_le_bytes()); // ema price
+        data.extend_from_slice(&conf.to_le_bytes()); // ema conf
+        data.extend_from_slice(&123u64.to_le_bytes()); // posted slot
+        data
+    }
+
+    #[test]
+    fn parses_fully_verified_update() {
+        let data = mock_price_update(&[1], 15_000_000_000, 5_000_000, -8, 1_700_000_000);
+        let price = parse_pyth_price(&data).unwrap();
+
+        assert_eq!(price.feed_id, [9u8; 32]);
+        assert_eq!(price.price, 15_000_000_000);
+        assert_eq!(price.conf, 5_000_000);
+        assert_eq!(price.exponent, -8);
+        assert_eq!(price.publish_time, 1_700_000_000);
+    }
+
+    #[test]
+    fn rejects_partial_verification_and_bad_data() {
+        let partial = mock_price_update(&[0, 3], 1, 0, -8, 0);
+        assert!(parse_pyth_price(&partial).is_err());
+
+        let mut bad_discriminator = mock_price_update(&[1], 1, 0, -8, 0);
+        bad_discriminator[0] ^= 1;
+        assert!(parse_pyth_price(&bad_discriminator).is_err());
+
+        let truncated = &mock_price_update(&[1], 1, 0, -8, 0)[..60];
+        assert!(parse_pyth_price(truncated).is_err());
+    }
+
+    #[test]
+    fn enforces_staleness_and_confidence() {
+        let end_time = 1_700_000_000;
+        let data = mock_price_update(&[1], 150_00000000, 10_00000000, -8, end_time + 30);
+        let price = parse_pyth_price(&data).unwrap();
+
+        // conf is 10 / 150 = 6.67% of the price
+        assert!(validate_pyth_price(&price, end_time, 60, 700).is_ok());
+        assert!(validate_pyth_price(&price, end_time, 60, 600).is_err());
+        assert!(validate_pyth_price(&price, end_time, 29, 700).is_err());
+        assert!(validate_pyth_price(&price, end_time + 31, 60, 700).is_err());
+    }
+
+    fn mock_pull_feed(authority: Pubkey, queue: Pubkey, value: i128, slot: u64, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; PULL_FEED_LEN];
+        data[..8].copy_from_slice(&PULL_FEED_DISCRIMINATOR);
+        data[PULL_FEED_AUTHORITY_OFFSET..PULL_FEED_AUTHORITY_OFFSET + 32].copy_from_slice(authority.as_ref());
+        data[PULL_FEED_QUEUE_OFFSET..PULL_FEED_QUEUE_OFFSET + 32].copy_from_slice(queue.as_ref());
+        data[PULL_FEED_LAST_UPDATE_OFFSET..PULL_FEED_LAST_UPDATE_OFFSET + 8].copy_from_slice(&timestamp.to_le_bytes());
+        data[PULL_FEED_RESULT_VALUE_OFFSET..PULL_FEED_RESULT_VALUE_OFFSET + 16].copy_from_slice(&value.to_le_bytes());
+        data[PULL_FEED_RESULT_SLOT_OFFSET..PULL_FEED_RESULT_SLOT_OFFSET + 8].copy_from_slice(&slot.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parses_switchboard_pull_feed() {
+        let authority = Pubkey::new_unique();
+        let queue = Pubkey::new_unique();
+        let data = mock_pull_feed(authority, queue, -42_500_000_000_000_000_000, 1_000, 1_700_000_000);
+        let result = parse_switchboard_feed(&data).unwrap();
+
+        assert_eq!(result.authority, authority);
+        assert_eq!(result.queue, queue);
+        assert_eq!(result.value, -42_500_000_000_000_000_000);
+        assert_eq!(result.slot, 1_000);
+        assert_eq!(result.timestamp, 1_700_000_000);
+
+        assert!(parse_switchboard_feed(&data[..PULL_FEED_LEN - 1]).is_err());
+        let mut bad_discriminator = data.clone();
+        bad_discriminator[0] ^= 1;
+        assert!(parse_switchboard_feed(&bad_discriminator).is_err());
+    }
+
+    #[test]
+    fn enforces_switchboard_freshness() {
+        let end_time = 1_700_000_000;
+        let data = mock_pull_feed(Pubkey::new_unique(), Pubkey::new_unique(), 3 * 10i128.pow(18), 1_000, end_time + 5);
+        let result = parse_switchboard_feed(&data).unwrap();
+
+        assert!(validate_switchboard_result(&result, end_time, 1_050, 50).is_ok());
+        assert!(validate_switchboard_result(&result, end_time, 1_051, 50).is_err());
+        assert!(validate_switchboard_result(&result, end_time + 6, 1_000, 50).is_err());
+
+        // 3 goals vs a 2.5 threshold
+        let threshold = PriceThreshold { value: 25, exponent: -1, comparison: Comparison::Above };
+        assert!(threshold.is_met(result.value, -SWITCHBOARD_DECIMALS).unwrap());
+    }
+
+    #[test]
+    fn compares_across_exponents() {
+        // $150.00 threshold with exponent -2 against a price of $150.5 with exponent -8
+        let above = PriceThreshold { value: 15_000, exponent: -2, comparison: Comparison::Above };
+        let below = PriceThreshold { value: 15_000, exponent: -2, comparison: Comparison::Below };
+
+        assert!(above.is_met(150_50000000, -8).unwrap());
+        assert!(!below.is_met(150_50000000, -8).unwrap());
+
+        // Equal price meets neither side
+        assert!(!above.is_met(150_00000000, -8).unwrap());
+        assert!(!below.is_met(150_00000000, -8).unwrap());
+    }
+}
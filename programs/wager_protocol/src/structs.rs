@@ -4,6 +4,11 @@ use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{Mint, Token, TokenAccount};
 use anchor_lang::prelude::*;
 use anchor_lang::Discriminator;
+use crate::error::ErrorCode;
+use crate::constants::*;
+use crate::asset;
+use crate::lmsr;
+use crate::PRECISION;
 
 
 #[account]
@@ -12,17 +17,659 @@ pub struct Market {
     pub bump: u8,               // 1
     pub creator: Pubkey,        // 32
     pub question: String,       // 4 + up to N
-    pub outcomes: Vec<String>,  // 4 + each string
+    pub outcomes: Vec<String>,  // 4 + N * (4 + 50)
     pub end_time: i64,          // 8
     pub resolved: bool,         // 1
     pub winning_outcome: Option<u8>, // 1 + 1
     pub total_volume: u64,      // 8
-    pub outcome_pools: Vec<u64>,// 4 + (2*8)
+    pub outcome_pools: Vec<u64>,// 4 + (N*8)
     pub position_count: u64,    // 8
+    pub paused: bool,           // 1
+    pub emergency: bool,        // 1 (irreversible, refunds only)
+    pub market_type: MarketType,// 1 + 16
+    pub resolved_value: Option<i64>, // 1 + 8 (scalar markets only)
+    pub voided: bool,           // 1 (resolved as invalid, stakes refundable)
+    pub resolution_deadline: i64, // 8 (anyone can void the market after this)
+    pub token_mint: Pubkey,     // 32 (collateral mint)
+    pub proposal: Option<ResolutionProposal>, // 1 + 92
+    pub resolution_source: ResolutionSource,  // 1 + 226
+    pub outcome_mint_count: u8, // 1 (outcome mints created, all at once by init_outcome_mints)
+    pub lp_reserve: Vec<u64>,   // 4 + (N*8) (shares of each outcome held for liquidity providers outside the pools)
+    pub lp_fees: u64,           // 8 (AMM fees owed to liquidity providers, held in escrow)
+    pub seed_shares: u64,       // 8 (liquidity units owned by the creator's seed or LMSR subsidy)
+    pub outcome_shares: Vec<u64>, // 4 + (N*8) (shares of each outcome held by traders, outcome_pools are the AMM reserves)
+    pub pricing: PricingMode,   // 1 + 8
+    pub subsidy: u64,           // 8 (LMSR subsidy funded by the creator, b * ln(N))
+    pub order_count: u64,       // 8 (limit orders placed so far, used as order id)
+    pub pending_fills: u64,     // 8 (order book fills waiting for consume_book_events)
+    pub offer_count: u64,       // 8 (peer-to-peer offers made so far, used as offer id)
+    pub parent: Option<ParentCondition>, // 1 + 33 (conditional markets only pay out if this holds)
+    pub lp_shares: u64,         // 8 (liquidity units outstanding, seed_shares plus the LP mint supply)
+    pub outcome_costs: Vec<u64>, // 4 + (N*8) (collateral paid for the shares in outcome_shares, refunded if voided)
+    pub price_snapshot: Vec<u64>, // 4 + (N*8) (constant product prices before the first trade of the snapshot interval, parlays price on them)
+    pub snapshot_time: i64,     // 8 (when price_snapshot was taken, 0 before the first trade)
+    pub parlay_legs: u64,       // 8 (legs of open parlays on this market, it cannot be made conditional while any remain)
+}
+
+/// Who (or what) sets the winning outcome of a market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResolutionSource {
+    /// The creator proposes the outcome (optimistic resolution with disputes)
+    Creator,
+    /// Binary market resolved permissionlessly from a Pyth price: outcome 0 if the threshold is met, 1 otherwise
+    Pyth {
+        price_account: Pubkey,    // 32 (PriceUpdateV2 account)
+        feed_id: [u8; 32],        // 32
+        threshold: PriceThreshold,// 13
+        max_staleness: i64,       // 8 (seconds after end_time the price may be published)
+        max_confidence_bps: u16,  // 2 (max conf / price)
+    },
+    /// Binary market resolved permissionlessly from a Switchboard on-demand pull feed, same outcome mapping as Pyth
+    Switchboard {
+        feed: Pubkey,             // 32 (PullFeedAccountData account)
+        queue: Pubkey,            // 32 (queue the feed's oracles must belong to)
+        authority: Pubkey,        // 32 (expected feed authority, it can change the feed's jobs)
+        threshold: PriceThreshold,// 13 (compared against the feed's 18-decimal value)
+        max_staleness_slots: u64, // 8
+    },
+    /// Categorical market resolved once `threshold` of the first `resolver_count` resolvers vote for the same outcome
+    Committee {
+        resolvers: [Pubkey; MAX_RESOLVERS], // 7 * 32 (unused slots are Pubkey::default())
+        resolver_count: u8,       // 1
+        threshold: u8,            // 1
+    },
+}
+
+impl ResolutionSource {
+    /// Index of `key` in the resolver set of a committee market.
+    pub fn resolver_index(&self, key: &Pubkey) -> Option<usize> {
+        match self {
+            ResolutionSource::Committee { resolvers, resolver_count, .. } => {
+                resolvers[..*resolver_count as usize].iter().position(|r| r == key)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PriceThreshold {
+    pub value: i64,               // 8
+    pub exponent: i32,            // 4 (threshold = value * 10^exponent)
+    pub comparison: Comparison,   // 1
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// Creator liquidity deposited at market creation, split over the outcomes by `odds_bps`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct SeedLiquidity {
+    pub amount: u64,
+    pub odds_bps: Vec<u16>, // initial probability of each outcome, summing to 10_000
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ResolutionProposal {
+    pub proposer: Pubkey,         // 32
+    pub outcome: Option<u8>,      // 1 + 1 (categorical markets)
+    pub value: Option<i64>,       // 1 + 8 (scalar markets)
+    pub bond: u64,                // 8 (posted by the proposer, matched by a disputer)
+    pub dispute_deadline: i64,    // 8
+    pub disputer: Option<Pubkey>, // 1 + 32
+}
+
+/// Parent market and outcome a conditional market depends on
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParentCondition {
+    pub market: Pubkey,           // 32
+    pub outcome: u8,              // 1
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MarketType {
+    /// Winner-takes-all over 2..=16 outcomes
+    Categorical,
+    /// Outcome 0 is LONG, outcome 1 is SHORT; paid out linearly on where the value falls in the range
+    Scalar { lower_bound: i64, upper_bound: i64 },
+}
+
+/// How the market prices bets and withdrawals
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PricingMode {
+    /// Constant product over the outcome pools
+    ConstantProduct,
+    /// Hanson's logarithmic market scoring rule over the outstanding shares. Winning shares pay
+    /// one unit each; the creator funds `b * ln(N)` up front, its maximum loss.
+    Lmsr { b: u64 },
+    /// Binary markets only: bids and asks for the first outcome on an `OrderBook`, the second
+    /// outcome implied. Every fill mints one share of each outcome for one unit of collateral.
+    OrderBook,
 }
 
 impl Market {
-    pub const INIT_SPACE: usize = 8 + 1 + 32 + (4 + 200) + (4 + 2 * (4 + 50)) + 8 + 1 + 2 + 8 + (4 + 16) + 8;
+    /// Account space for a market with `outcome_count` outcomes.
+    pub const fn space(outcome_count: usize) -> usize {
+        8 + 1 + 32
+            + (4 + MAX_QUESTION_LEN)
+            + (4 + outcome_count * (4 + MAX_OUTCOME_LEN))
+            + 8 + 1 + 2 + 8
+            + (4 + outcome_count * 8)
+            + 8 + 1 + 1
+            + (1 + 16)
+            + (1 + 8)
+            + 1 + 8
+            + 32
+            + (1 + 92)
+            + (1 + 226)
+            + 1
+            + (4 + outcome_count * 8) + 8
+            + 8
+            + (4 + outcome_count * 8)
+            + (1 + 8) + 8
+            + 8 + 8 + 8
+            + (1 + 33)
+            + 8
+            + (4 + outcome_count * 8)
+            + (4 + outcome_count * 8) + 8
+            + 8
+    }
+
+    /// Markets whose every outcome has an SPL mint trade outcome tokens instead of Positions.
+    pub fn has_outcome_tokens(&self) -> bool {
+        self.outcome_mint_count as usize == self.outcomes.len()
+    }
+
+    /// Earliest time `void_expired_market` can void the market. A disputed proposal gets one more
+    /// dispute window for arbitration once both the resolution and dispute deadlines have passed.
+    pub fn void_deadline(&self, dispute_window: i64) -> Result<i64> {
+        let Some(proposal) = self.proposal else {
+            return Ok(self.resolution_deadline);
+        };
+        require!(proposal.disputer.is_some(), ErrorCode::ResolutionPending);
+
+        self.resolution_deadline.max(proposal.dispute_deadline)
+            .checked_add(dispute_window).ok_or(ErrorCode::AmountOverflow.into())
+    }
+
+    /// Collateral recorded as paid for every share held by traders.
+    pub fn outstanding_cost(&self) -> Result<u64> {
+        self.outcome_costs.iter().try_fold(0u64, |acc, cost| acc.checked_add(*cost))
+            .ok_or(ErrorCode::AmountOverflow.into())
+    }
+
+    /// Escrow left to the liquidity providers of a voided market once every share held by traders
+    /// is refunded at cost, excluding LP fees. Nothing is left if the escrow falls short of the costs.
+    pub fn residual_escrow(&self, escrow_balance: u64) -> Result<u64> {
+        Ok(escrow_balance.saturating_sub(self.lp_fees).saturating_sub(self.outstanding_cost()?))
+    }
+
+    /// Refund owed in a voided or emergency market for shares that carry `cost`, before they are
+    /// released. Traders who sold at a profit can leave the escrow short of every recorded cost, so
+    /// the escrow less LP fees is shared pro rata to cost, never more than `cost` each. Claims
+    /// leave the ratio unchanged, so the order they come in does not matter.
+    pub fn refund_value(&self, cost: u64, escrow_balance: u64) -> Result<u64> {
+        let outstanding = self.outstanding_cost()?;
+        require!(cost <= outstanding, ErrorCode::AmountOverflow);
+        if cost == 0 {
+            return Ok(0);
+        }
+
+        let available = escrow_balance.saturating_sub(self.lp_fees) as u128;
+        let refund = (cost as u128)
+            .checked_mul(available).ok_or(ErrorCode::AmountOverflow)?
+            / outstanding as u128;
+        Ok(refund.min(cost as u128) as u64)
+    }
+
+    /// Book `amount` shares of outcome `idx` bought by traders for `cost`.
+    pub fn hold_shares(&mut self, idx: usize, amount: u64, cost: u64) -> Result<()> {
+        self.outcome_shares[idx] = self.outcome_shares[idx].checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+        self.outcome_costs[idx] = self.outcome_costs[idx].checked_add(cost).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(())
+    }
+
+    /// Take `amount` shares of outcome `idx` and the `cost` they carry off the traders' books.
+    pub fn release_shares(&mut self, idx: usize, amount: u64, cost: u64) -> Result<()> {
+        self.outcome_shares[idx] = self.outcome_shares[idx].checked_sub(amount).ok_or(ErrorCode::AmountOverflow)?;
+        self.outcome_costs[idx] = self.outcome_costs[idx].checked_sub(cost).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(())
+    }
+
+    /// Book `amount` complete sets split from as much collateral: one share of every outcome for
+    /// each unit, held by traders like any other share. The collateral is spread evenly over the
+    /// outcomes as their cost, the first outcome taking the rounding.
+    pub fn split_sets(&mut self, amount: u64) -> Result<()> {
+        let n = self.outcomes.len() as u64;
+        for i in 0..self.outcomes.len() {
+            let cost = if i == 0 { amount / n + amount % n } else { amount / n };
+            self.hold_shares(i, amount, cost)?;
+        }
+        self.total_volume = self.total_volume.checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(())
+    }
+
+    /// Cost carried by `amount` complete sets held by traders, at the average cost of each outcome.
+    pub fn set_cost(&self, amount: u64) -> Result<u64> {
+        require!(self.outcome_shares.iter().all(|held| *held >= amount), ErrorCode::InsufficientCompleteSets);
+        (0..self.outcomes.len()).try_fold(0u64, |acc, i| {
+            acc.checked_add(self.average_cost(i, amount)?).ok_or(ErrorCode::AmountOverflow.into())
+        })
+    }
+
+    /// Take `amount` complete sets held by traders off the books and return the cost they carried,
+    /// at the average cost of each outcome.
+    pub fn merge_sets(&mut self, amount: u64) -> Result<u64> {
+        require!(self.outcome_shares.iter().all(|held| *held >= amount), ErrorCode::InsufficientCompleteSets);
+
+        let mut cost = 0u64;
+        for i in 0..self.outcomes.len() {
+            let released = self.average_cost(i, amount)?;
+            self.release_shares(i, amount, released)?;
+            cost = cost.checked_add(released).ok_or(ErrorCode::AmountOverflow)?;
+        }
+        self.total_volume = self.total_volume.checked_sub(amount).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(cost)
+    }
+
+    /// Cost of `amount` outcome tokens of `idx`: the average collateral paid per share held,
+    /// rounded down. Tokens are fungible so they carry no cost of their own.
+    pub fn average_cost(&self, idx: usize, amount: u64) -> Result<u64> {
+        let held = self.outcome_shares[idx];
+        require!(amount <= held, ErrorCode::AmountOverflow);
+        if amount == 0 {
+            return Ok(0);
+        }
+
+        Ok((self.outcome_costs[idx] as u128)
+            .checked_mul(amount as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(held as u128).ok_or(ErrorCode::AmountOverflow)? as u64)
+    }
+
+    /// Spot price of outcome `idx` on `PRECISION`. Each outcome is priced inversely to its pool,
+    /// `(1 / pool_idx) / sum(1 / pool_j)`, so the prices of all outcomes sum to one.
+    pub fn price(&self, idx: usize) -> Result<u64> {
+        require!(self.pricing == PricingMode::ConstantProduct, ErrorCode::UnsupportedPricingMode);
+        require!(self.outcome_pools.iter().all(|pool| *pool > 0), ErrorCode::InsufficientLiquidity);
+
+        let pool = self.outcome_pools[idx] as u128;
+        let inverse_sum = self.outcome_pools
+            .iter()
+            .try_fold(0u128, |acc, other| acc.checked_add(pool * PRECISION / *other as u128))
+            .ok_or(ErrorCode::AmountOverflow)?;
+
+        Ok((PRECISION * PRECISION / inverse_sum) as u64)
+    }
+
+    /// Snapshot the constant product prices before the first trade of every
+    /// `PRICE_SNAPSHOT_INTERVAL`. Call it before any trade moves the pools.
+    pub fn record_prices(&mut self, now: i64) -> Result<()> {
+        if self.pricing != PricingMode::ConstantProduct
+            || now < self.snapshot_time.saturating_add(PRICE_SNAPSHOT_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.price_snapshot = (0..self.outcomes.len()).map(|i| self.price(i)).collect::<Result<_>>()?;
+        self.snapshot_time = now;
+        Ok(())
+    }
+
+    /// Decimal parlay odds of outcome `idx` on `PRECISION`: `1 / price` at the higher of its spot
+    /// and snapshot prices, less the AMM fee, capped at `MAX_PARLAY_LEG_ODDS`. Fails if the spot
+    /// price has moved more than `MAX_PARLAY_PRICE_MOVE_BPS` from the snapshot, so pools moved
+    /// within a transaction cannot price a leg.
+    pub fn leg_odds(&self, idx: usize, amm_fee_bps: u16) -> Result<u64> {
+        let spot = self.price(idx)? as u128;
+        let price = match self.price_snapshot[idx] as u128 {
+            // Never traded: the pools are still as seeded
+            0 => spot,
+            snapshot => {
+                require!(
+                    spot.abs_diff(snapshot) * BPS_DENOMINATOR as u128 <= snapshot * MAX_PARLAY_PRICE_MOVE_BPS as u128,
+                    ErrorCode::LegPriceMoved
+                );
+                spot.max(snapshot)
+            }
+        };
+        require!(price > 0, ErrorCode::InsufficientLiquidity);
+
+        let odds = (PRECISION * PRECISION / price)
+            .checked_mul(BPS_DENOMINATOR as u128 - amm_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+            / BPS_DENOMINATOR as u128;
+        Ok(odds.min(MAX_PARLAY_LEG_ODDS as u128) as u64)
+    }
+
+    /// Price a purchase of outcome `idx` with `amount` of collateral, the AMM fee coming off first.
+    /// On the constant product pools the rest mints as many complete sets into every pool, then the
+    /// shares bought leave the outcome's own pool so the product of the pools stays constant: the
+    /// outcome's price goes up and every other price down. LMSR markets price on their cost function.
+    pub fn buy_quote(&self, idx: usize, amount: u64, amm_fee_bps: u16) -> Result<BuyQuote> {
+        let fee = (amount as u128)
+            .checked_mul(amm_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+        let net = amount.checked_sub(fee).ok_or(ErrorCode::AmountOverflow)?;
+
+        let shares = match self.pricing {
+            PricingMode::ConstantProduct => {
+                require!(self.outcome_pools.iter().all(|pool| *pool > 0), ErrorCode::InsufficientLiquidity);
+
+                // Pool of the outcome once every other pool grew by `net` at a constant product,
+                // rounded up so the pools never give out more than the curve allows
+                let pool = self.outcome_pools[idx] as u128;
+                let mut new_pool = pool;
+                for (_, other) in self.outcome_pools.iter().enumerate().filter(|(j, _)| *j != idx) {
+                    let other = *other as u128;
+                    new_pool = new_pool
+                        .checked_mul(other).ok_or(ErrorCode::AmountOverflow)?
+                        .div_ceil(other + net as u128);
+                }
+
+                (pool + net as u128 - new_pool).try_into().map_err(|_| ErrorCode::AmountOverflow)?
+            }
+            PricingMode::Lmsr { b } => lmsr::buy_shares(&self.outcome_shares, b, idx, net)?,
+            PricingMode::OrderBook => return err!(ErrorCode::UnsupportedPricingMode),
+        };
+
+        Ok(BuyQuote { shares, fee })
+    }
+
+    /// Book a purchase priced by `buy_quote`: `amount` of collateral buys `quote.shares` of outcome
+    /// `idx` and is their cost. The AMM fee stays in escrow for the liquidity providers.
+    pub fn apply_buy(&mut self, idx: usize, amount: u64, quote: &BuyQuote) -> Result<()> {
+        let net = amount.checked_sub(quote.fee).ok_or(ErrorCode::AmountOverflow)?;
+        if self.pricing == PricingMode::ConstantProduct {
+            for pool in self.outcome_pools.iter_mut() {
+                *pool = pool.checked_add(net).ok_or(ErrorCode::AmountOverflow)?;
+            }
+            self.outcome_pools[idx] = self.outcome_pools[idx].checked_sub(quote.shares).ok_or(ErrorCode::InsufficientLiquidity)?;
+        }
+        self.hold_shares(idx, quote.shares, amount)?;
+        self.total_volume = self.total_volume.checked_add(net).ok_or(ErrorCode::AmountOverflow)?;
+        self.lp_fees = self.lp_fees.checked_add(quote.fee).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(())
+    }
+
+    /// Price a sale of `amount` of outcome `idx` back to the constant product pools, or the LMSR
+    /// cost function. Both the AMM fee and the cancel fee are charged on the gross payout.
+    pub fn sell_quote(&self, idx: usize, amount: u64, amm_fee_bps: u16, cancel_fee_bps: u16) -> Result<SellQuote> {
+        let payout_gross = match self.pricing {
+            PricingMode::ConstantProduct => {
+                require!(self.outcome_pools.iter().all(|pool| *pool > 0), ErrorCode::InsufficientLiquidity);
+
+                // The shares go into their pool and the payout burns as many complete sets out of
+                // every pool: the largest payout that keeps the product of the pools
+                let mut low = 0u64;
+                let mut high = self.outcome_pools
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != idx)
+                    .map(|(_, pool)| *pool)
+                    .fold(amount, u64::min);
+                while low < high {
+                    let mid = low + (high - low).div_ceil(2);
+                    if self.sell_keeps_product(idx, amount, mid) {
+                        low = mid;
+                    } else {
+                        high = mid - 1;
+                    }
+                }
+                low as u128
+            }
+            PricingMode::Lmsr { b } => lmsr::sell_payout(&self.outcome_shares, b, idx, amount)? as u128,
+            PricingMode::OrderBook => return err!(ErrorCode::UnsupportedPricingMode),
+        };
+
+        let amm_fee = payout_gross
+            .checked_mul(amm_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)?;
+        let cancel_fee = payout_gross
+            .checked_mul(cancel_fee_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+            .checked_div(BPS_DENOMINATOR as u128).ok_or(ErrorCode::AmountOverflow)?;
+
+        let fee = amm_fee.checked_add(cancel_fee).ok_or(ErrorCode::AmountOverflow)?;
+        require!(fee <= u64::MAX as u128, ErrorCode::AmountOverflow);
+
+        let payout_gross = payout_gross as u64;
+        let fee = fee as u64;
+        Ok(SellQuote {
+            payout_gross,
+            fee,
+            amm_fee: amm_fee as u64,
+            payout_net: payout_gross.checked_sub(fee).ok_or(ErrorCode::AmountOverflow)?,
+        })
+    }
+
+    /// Whether the product of the pools holds once `amount` shares of outcome `idx` go into its pool
+    /// and `collateral` complete sets come out of every pool. The other pools shrink the product by
+    /// a ratio kept on `PRECISION^2` and rounded down, so rounding never favours the seller.
+    fn sell_keeps_product(&self, idx: usize, amount: u64, collateral: u64) -> bool {
+        let scale = PRECISION * PRECISION;
+        let collateral = collateral as u128;
+
+        let mut ratio = scale;
+        for (_, pool) in self.outcome_pools.iter().enumerate().filter(|(j, _)| *j != idx) {
+            let pool = *pool as u128;
+            ratio = ratio * pool.saturating_sub(collateral) / pool;
+        }
+
+        let pool = self.outcome_pools[idx] as u128;
+        ratio * (pool + amount as u128 - collateral) >= scale * pool
+    }
+
+    /// Book a trader's sale priced by `sell_quote`, `cost` being the part of their cost basis the
+    /// shares carried, and return the fee owed to the protocol.
+    pub fn apply_sell(&mut self, idx: usize, amount: u64, cost: u64, quote: &SellQuote) -> Result<u64> {
+        self.release_shares(idx, amount, cost)?;
+        self.sell_to_pool(idx, amount, quote)
+    }
+
+    /// Book the pool side of a sale priced by `sell_quote` and return the fee owed to the protocol.
+    /// The shares go into their pool and the gross payout burns as many complete sets out of every
+    /// pool. The AMM fee stays in escrow for the liquidity providers.
+    pub fn sell_to_pool(&mut self, idx: usize, amount: u64, quote: &SellQuote) -> Result<u64> {
+        if self.pricing == PricingMode::ConstantProduct {
+            self.outcome_pools[idx] = self.outcome_pools[idx].checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+            for pool in self.outcome_pools.iter_mut() {
+                *pool = pool.checked_sub(quote.payout_gross).ok_or(ErrorCode::InsufficientLiquidity)?;
+            }
+        }
+        self.total_volume = self.total_volume.checked_sub(quote.payout_gross).ok_or(ErrorCode::InsufficientLiquidity)?;
+        self.lp_fees = self.lp_fees.checked_add(quote.amm_fee).ok_or(ErrorCode::AmountOverflow)?;
+
+        quote.fee.checked_sub(quote.amm_fee).ok_or(ErrorCode::AmountOverflow.into())
+    }
+
+    /// Value of the shares held for liquidity providers, in the pools and outside them, at the
+    /// current prices.
+    pub fn liquidity_value(&self) -> Result<u64> {
+        let mut value = 0u128;
+        for i in 0..self.outcomes.len() {
+            let held = self.outcome_pools[i] as u128 + self.lp_reserve[i] as u128;
+            value = value
+                .checked_add(held * self.price(i)? as u128 / PRECISION).ok_or(ErrorCode::AmountOverflow)?;
+        }
+        value.try_into().map_err(|_| ErrorCode::AmountOverflow.into())
+    }
+
+    /// Add `amount` of collateral as liquidity without moving the prices and return the liquidity
+    /// units it is worth. The collateral mints as many complete sets: each pool keeps its share of
+    /// them in proportion to the largest pool and the rest are held for the providers.
+    pub fn add_liquidity(&mut self, amount: u64) -> Result<u64> {
+        let units = if self.lp_shares == 0 {
+            amount
+        } else {
+            let value = self.liquidity_value()?.checked_add(self.lp_fees).ok_or(ErrorCode::AmountOverflow)?;
+            require!(value > 0, ErrorCode::InsufficientLiquidity);
+            (amount as u128)
+                .checked_mul(self.lp_shares as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(value as u128).ok_or(ErrorCode::AmountOverflow)?
+                .try_into().map_err(|_| ErrorCode::AmountOverflow)?
+        };
+        require!(units > 0, ErrorCode::InvalidAmount);
+
+        let largest = self.outcome_pools.iter().copied().max().unwrap_or(0);
+        for i in 0..self.outcomes.len() {
+            let pooled = if largest == 0 {
+                amount
+            } else {
+                (amount as u128)
+                    .checked_mul(self.outcome_pools[i] as u128).ok_or(ErrorCode::AmountOverflow)?
+                    .checked_div(largest as u128).ok_or(ErrorCode::AmountOverflow)? as u64
+            };
+            self.outcome_pools[i] = self.outcome_pools[i].checked_add(pooled).ok_or(ErrorCode::AmountOverflow)?;
+            self.lp_reserve[i] = self.lp_reserve[i].checked_add(amount - pooled).ok_or(ErrorCode::AmountOverflow)?;
+        }
+        self.total_volume = self.total_volume.checked_add(amount).ok_or(ErrorCode::AmountOverflow)?;
+        self.lp_shares = self.lp_shares.checked_add(units).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(units)
+    }
+
+    /// Take `units` of liquidity out of the market and return the shares of each outcome and the
+    /// LP fees they own, in proportion to the units outstanding. The prices do not move.
+    pub fn take_liquidity(&mut self, units: u64) -> Result<(Vec<u64>, u64)> {
+        require!(units > 0 && units <= self.lp_shares, ErrorCode::InsufficientLiquidity);
+        let total = self.lp_shares as u128;
+        let part = |held: u64| (held as u128 * units as u128 / total) as u64;
+
+        let mut shares = vec![0u64; self.outcomes.len()];
+        for (i, taken) in shares.iter_mut().enumerate() {
+            let pooled = part(self.outcome_pools[i]);
+            let reserved = part(self.lp_reserve[i]);
+            self.outcome_pools[i] -= pooled;
+            self.lp_reserve[i] -= reserved;
+            *taken = pooled + reserved;
+        }
+        let fees = part(self.lp_fees);
+        self.lp_fees -= fees;
+        self.lp_shares -= units;
+
+        Ok((shares, fees))
+    }
+
+    /// Sell liquidity provider `shares` taken out by `take_liquidity` back to the market before it
+    /// ends: the complete sets among them are merged at par and the rest sold to the pools at their
+    /// price, paying the same fees as a trader's sale. Returns the collateral released net of fees
+    /// and the cancel fee owed to the protocol. Fails if the pools were left empty.
+    pub fn exit_liquidity(&mut self, shares: &[u64], amm_fee_bps: u16, cancel_fee_bps: u16) -> Result<(u64, u64)> {
+        let sets = shares.iter().copied().min().unwrap_or(0);
+        self.total_volume = self.total_volume.checked_sub(sets).ok_or(ErrorCode::InsufficientLiquidity)?;
+
+        let mut value = sets;
+        let mut protocol_fee = 0u64;
+        for (i, amount) in shares.iter().enumerate() {
+            let excess = amount - sets;
+            if excess == 0 {
+                continue;
+            }
+            let quote = self.sell_quote(i, excess, amm_fee_bps, cancel_fee_bps)?;
+            let fee = self.sell_to_pool(i, excess, &quote)?;
+            value = value.checked_add(quote.payout_net).ok_or(ErrorCode::AmountOverflow)?;
+            protocol_fee = protocol_fee.checked_add(fee).ok_or(ErrorCode::AmountOverflow)?;
+        }
+        Ok((value, protocol_fee))
+    }
+
+    /// Put the creator's seed liquidity in the pools so the market opens at `odds_bps`. The seed
+    /// mints `amount` complete sets; each pool keeps them in inverse proportion to its outcome's odds
+    /// (the likeliest outcome has the smallest pool) and the rest are held for the creator.
+    /// Returns the total deposited.
+    pub fn seed_pools(&mut self, seed: &SeedLiquidity) -> Result<u64> {
+        let n = self.outcomes.len();
+        require!(seed.amount > 0 && seed.odds_bps.len() == n, ErrorCode::InvalidSeedLiquidity);
+        require!(seed.odds_bps.iter().all(|bps| *bps > 0), ErrorCode::InvalidSeedLiquidity);
+        let total_bps = seed.odds_bps.iter().map(|bps| *bps as u32).sum::<u32>();
+        require!(total_bps == BPS_DENOMINATOR as u32, ErrorCode::InvalidSeedLiquidity);
+
+        let min_bps = seed.odds_bps.iter().copied().min().ok_or(ErrorCode::InvalidSeedLiquidity)?;
+        for i in 0..n {
+            let pooled = (seed.amount as u128)
+                .checked_mul(min_bps as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(seed.odds_bps[i] as u128).ok_or(ErrorCode::AmountOverflow)? as u64;
+            require!(pooled > 0, ErrorCode::InvalidSeedLiquidity);
+
+            self.outcome_pools[i] = pooled;
+            self.lp_reserve[i] = seed.amount - pooled;
+        }
+        self.total_volume = self.total_volume.checked_add(seed.amount).ok_or(ErrorCode::AmountOverflow)?;
+        self.seed_shares = seed.amount;
+        self.lp_shares = self.lp_shares.checked_add(seed.amount).ok_or(ErrorCode::AmountOverflow)?;
+        Ok(seed.amount)
+    }
+
+    /// Value of liquidity provider shares once the market is resolved, at the same rates as
+    /// winning positions.
+    pub fn settled_value(&self, shares: &[u64]) -> Result<u64> {
+        shares.iter().enumerate().try_fold(0u64, |acc, (i, amount)| {
+            acc.checked_add(self.payout_value(i as u8, *amount)?).ok_or(ErrorCode::AmountOverflow.into())
+        })
+    }
+
+    /// Collateral `amount` shares of `outcome` are worth in a resolved market: one unit per winning
+    /// share, or the share's side of the range in a scalar market. Losing shares are worth nothing.
+    pub fn payout_value(&self, outcome: u8, amount: u64) -> Result<u64> {
+        match self.market_type {
+            MarketType::Categorical => {
+                let winning_outcome = self.winning_outcome.ok_or(ErrorCode::MarketNotResolved)?;
+                Ok(if outcome == winning_outcome { amount } else { 0 })
+            }
+            MarketType::Scalar { lower_bound, upper_bound } => {
+                let value = self.resolved_value.ok_or(ErrorCode::MarketNotResolved)?;
+                let range = (upper_bound as i128 - lower_bound as i128) as u128;
+                let offset = (value.clamp(lower_bound, upper_bound) as i128 - lower_bound as i128) as u128;
+                let side = if outcome == 0 { offset } else { range - offset };
+
+                (amount as u128)
+                    .checked_mul(side).ok_or(ErrorCode::AmountOverflow)?
+                    .checked_div(range).ok_or(ErrorCode::AmountOverflow)?
+                    .try_into().map_err(|_| ErrorCode::AmountOverflow.into())
+            }
+        }
+    }
+
+    /// What the creator gets back from a resolved LMSR market: the collateral and subsidy left once
+    /// every outstanding share is paid. Zero for other markets.
+    pub fn lmsr_surplus(&self) -> Result<u64> {
+        if !matches!(self.pricing, PricingMode::Lmsr { .. }) {
+            return Ok(0);
+        }
+
+        let mut liability = 0u64;
+        for i in 0..self.outcomes.len() {
+            // Losing outcomes are owed nothing; only the winning side (or both sides of a scalar
+            // market) adds to what the shares can claim
+            let owed = self.payout_value(i as u8, self.outcome_shares[i])?;
+            liability = liability.checked_add(owed).ok_or(ErrorCode::AmountOverflow)?;
+        }
+
+        // The cost function caps what traders can win at the subsidy, so the pot always covers them
+        self.total_volume
+            .checked_add(self.subsidy).ok_or(ErrorCode::AmountOverflow)?
+            .checked_sub(liability).ok_or(ErrorCode::InsufficientEscrow.into())
+    }
+
+    /// Gross payout (before protocol fee) of `amount` shares of `outcome` in a resolved market.
+    pub fn winnings(&self, outcome: u8, amount: u64) -> Result<u64> {
+        let payout = self.payout_value(outcome, amount)?;
+        require!(payout > 0, ErrorCode::InvalidOutcome);
+        Ok(payout)
+    }
+}
+
+/// Outcome of pricing a purchase against the market.
+pub struct BuyQuote {
+    pub shares: u64,
+    pub fee: u64,
+}
+
+/// Outcome of pricing a sale against the market pools.
+pub struct SellQuote {
+    pub payout_gross: u64,
+    pub fee: u64,
+    pub amm_fee: u64,
+    pub payout_net: u64,
 }
 
 #[account]
@@ -35,10 +682,16 @@ pub struct Protocol {
     pub amm_fee: u16, //2 fee when swapping tokens in AMM
     pub market_count: u64,        // 8
     pub dev_recipient: Pubkey,    // 32
+    pub pending_authority: Option<Pubkey>, // 1 + 32 (set by propose_authority)
+    pub paused: bool,             // 1 (halts trading on every market)
+    pub dev_fee_share_bps: u16,   // 2 (share of every fee sent to dev_recipient, rest goes to the authority)
+    pub dispute_window: i64,      // 8 (seconds a proposed resolution can be disputed)
+    pub dispute_bond: u64,        // 8 (bond posted by proposer and disputer, in the market's token)
+    pub arbiter: Pubkey,          // 32 (settles disputes alongside the authority, default = none)
 }
 
 impl Protocol {
-    pub const INIT_SPACE: usize = 32 + 32 + 2 + 2 + 2 + 8 + 32;
+    pub const INIT_SPACE: usize = 32 + 32 + 2 + 2 + 2 + 8 + 32 + (1 + 32) + 1 + 2 + 8 + 8 + 32;
 }
 
 #[account]
@@ -51,9 +704,292 @@ pub struct Position {
     pub amount: u64,                  // 8
     pub claimed: bool,                // 1
     pub ts: i64,                      // 8 (timestamp when bet placed or last increased)
+    pub asset: Option<Pubkey>,        // 1 + 32 (Core asset whose holder controls the position)
+    pub locked: u64,                  // 8 (shares escrowed by open sell orders)
+    pub cost: u64,                    // 8 (collateral paid for the shares held, refunded if the market is voided)
+    pub open_orders: u32,             // 4 (limit orders filling into or selling from the position)
 }
 impl Position {
-    pub const INIT_SPACE: usize = 8 + 1 + 32 + 32 + 1 + 8 + 1 + 8 + 8 + 8;
+    pub const INIT_SPACE: usize = 8 + 1 + 32 + 32 + 1 + 8 + 1 + 8 + 8 + 8 + (1 + 32) + 8 + 8 + 4;
+
+    /// Shares not locked by open sell orders.
+    pub fn free(&self) -> Result<u64> {
+        self.amount.checked_sub(self.locked).ok_or(ErrorCode::AmountOverflow.into())
+    }
+
+    /// Take `amount` shares off the position and return the part of its cost they carry, rounded
+    /// up so the shares left never carry more than their share of it.
+    pub fn release(&mut self, amount: u64) -> Result<u64> {
+        require!(amount > 0 && amount <= self.amount, ErrorCode::WithdrawAmountExceedsPosition);
+        let cost = (self.cost as u128 * amount as u128).div_ceil(self.amount as u128) as u64;
+
+        self.amount -= amount;
+        self.cost -= cost;
+        Ok(cost)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OrderSide {
+    /// Spend escrowed collateral on shares
+    Buy,
+    /// Sell shares locked on a position
+    Sell,
+}
+
+/// Resting limit order on one outcome of a Position market
+#[account]
+pub struct Order {
+    pub id: u64,                      // 8 (unique per market)
+    pub bump: u8,                     // 1
+    pub market: Pubkey,               // 32
+    pub owner: Pubkey,                // 32
+    pub position: Pubkey,             // 32 (receives bought shares, or holds the shares for sale)
+    pub side: OrderSide,              // 1
+    pub outcome: u8,                  // 1
+    pub limit_price_bps: u16,         // 2 (max price per share for buys, min for sells, in bps of one collateral unit)
+    pub remaining: u64,               // 8 (collateral left to spend, or shares left to sell)
+    pub filled: u64,                  // 8 (shares bought, or collateral received in the order vault)
+    pub expiry: i64,                  // 8
+}
+impl Order {
+    pub const INIT_SPACE: usize = 8 + 1 + 32 + 32 + 32 + 1 + 1 + 2 + 8 + 8 + 8;
+
+    /// PDA signer seeds of the order, which owns its vault.
+    pub fn signer_seeds<'a>(&'a self, id_bytes: &'a [u8; 8], bump: &'a [u8; 1]) -> [&'a [u8]; 4] {
+        [b"order", self.market.as_ref(), id_bytes, bump]
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BookSide {
+    /// Buys the first outcome at `price_bps`
+    Bid,
+    /// Sells the first outcome at `price_bps`, i.e. buys the second at the complement
+    Ask,
+}
+
+impl BookSide {
+    /// Outcome whose shares the order receives when it fills.
+    pub fn outcome(self) -> u8 {
+        match self {
+            BookSide::Bid => 0,
+            BookSide::Ask => 1,
+        }
+    }
+
+    /// Collateral this side pays for `quantity` shares traded at `price_bps`.
+    /// Exact, since quantities are whole `BOOK_LOT_SIZE` lots.
+    pub fn collateral(self, price_bps: u16, quantity: u64) -> Result<u64> {
+        let price = match self {
+            BookSide::Bid => price_bps,
+            BookSide::Ask => BPS_DENOMINATOR - price_bps,
+        };
+        Ok(quantity.checked_mul(price as u64).ok_or(ErrorCode::AmountOverflow)? / BPS_DENOMINATOR as u64)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct BookOrder {
+    pub id: u64,                      // 8 (unique per book)
+    pub owner: Pubkey,                // 32
+    pub position: Pubkey,             // 32 (receives the shares of every fill)
+    pub price_bps: u16,               // 2 (price of the first outcome)
+    pub quantity: u64,                // 8 (shares left, whole lots)
+}
+impl BookOrder {
+    pub const SPACE: usize = 8 + 32 + 32 + 2 + 8;
+}
+
+/// A match waiting to be settled into positions by `consume_book_events`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct FillEvent {
+    pub maker_order: u64,             // 8
+    pub bid_position: Pubkey,         // 32 (gets `quantity` shares of the first outcome)
+    pub ask_position: Pubkey,         // 32 (gets `quantity` shares of the second outcome)
+    pub price_bps: u16,               // 2
+    pub quantity: u64,                // 8
+}
+impl FillEvent {
+    pub const SPACE: usize = 8 + 32 + 32 + 2 + 8;
+}
+
+/// Central limit order book of an `OrderBook` market. Resting collateral sits in the book vault
+/// until a fill is settled.
+#[account]
+pub struct OrderBook {
+    pub market: Pubkey,               // 32
+    pub bump: u8,                     // 1
+    pub order_count: u64,             // 8 (orders placed so far, used as order id)
+    pub bids: Vec<BookOrder>,         // 4 + MAX_BOOK_ORDERS * 82 (highest price first, then oldest)
+    pub asks: Vec<BookOrder>,         // 4 + MAX_BOOK_ORDERS * 82 (lowest price first, then oldest)
+    pub events: Vec<FillEvent>,       // 4 + MAX_BOOK_EVENTS * 82 (oldest first)
+}
+impl OrderBook {
+    pub const INIT_SPACE: usize = 32 + 1 + 8
+        + 2 * (4 + MAX_BOOK_ORDERS * BookOrder::SPACE)
+        + (4 + MAX_BOOK_EVENTS * FillEvent::SPACE);
+
+    /// Match an incoming order against the opposite side, best price first, at the makers' prices.
+    /// Queues a fill event per match and returns the shares filled and the collateral they cost.
+    pub fn match_order(&mut self, side: BookSide, price_bps: u16, quantity: u64, position: Pubkey) -> Result<(u64, u64)> {
+        let makers = match side {
+            BookSide::Bid => &mut self.asks,
+            BookSide::Ask => &mut self.bids,
+        };
+
+        let mut remaining = quantity;
+        let mut cost = 0u64;
+        while remaining > 0 {
+            let Some(maker) = makers.first_mut() else { break };
+            let crosses = match side {
+                BookSide::Bid => price_bps >= maker.price_bps,
+                BookSide::Ask => price_bps <= maker.price_bps,
+            };
+            if !crosses {
+                break;
+            }
+            require!(self.events.len() < MAX_BOOK_EVENTS, ErrorCode::EventQueueFull);
+
+            let fill = remaining.min(maker.quantity);
+            cost = cost.checked_add(side.collateral(maker.price_bps, fill)?).ok_or(ErrorCode::AmountOverflow)?;
+            let (bid_position, ask_position) = match side {
+                BookSide::Bid => (position, maker.position),
+                BookSide::Ask => (maker.position, position),
+            };
+            self.events.push(FillEvent {
+                maker_order: maker.id,
+                bid_position,
+                ask_position,
+                price_bps: maker.price_bps,
+                quantity: fill,
+            });
+
+            maker.quantity -= fill;
+            remaining -= fill;
+            if maker.quantity == 0 {
+                makers.remove(0);
+            }
+        }
+
+        Ok((quantity - remaining, cost))
+    }
+
+    /// Rest an order behind every order at the same or a better price.
+    pub fn insert(&mut self, side: BookSide, order: BookOrder) -> Result<()> {
+        let orders = match side {
+            BookSide::Bid => &mut self.bids,
+            BookSide::Ask => &mut self.asks,
+        };
+        require!(orders.len() < MAX_BOOK_ORDERS, ErrorCode::OrderBookFull);
+
+        let at = orders
+            .iter()
+            .position(|o| match side {
+                BookSide::Bid => o.price_bps < order.price_bps,
+                BookSide::Ask => o.price_bps > order.price_bps,
+            })
+            .unwrap_or(orders.len());
+        orders.insert(at, order);
+        Ok(())
+    }
+
+    /// Take a resting order off the book.
+    pub fn remove(&mut self, id: u64) -> Result<(BookSide, BookOrder)> {
+        if let Some(i) = self.bids.iter().position(|o| o.id == id) {
+            return Ok((BookSide::Bid, self.bids.remove(i)));
+        }
+        if let Some(i) = self.asks.iter().position(|o| o.id == id) {
+            return Ok((BookSide::Ask, self.asks.remove(i)));
+        }
+        err!(ErrorCode::InvalidOrder)
+    }
+}
+
+/// Peer-to-peer wager on a market outcome. The maker backs `outcome`, the taker every other
+/// outcome; the winner takes both stakes once the market resolves.
+#[account]
+pub struct Offer {
+    pub id: u64,                      // 8 (unique per market)
+    pub bump: u8,                     // 1
+    pub market: Pubkey,               // 32
+    pub maker: Pubkey,                // 32
+    pub taker: Option<Pubkey>,        // 1 + 32 (designated taker, then whoever accepted)
+    pub outcome: u8,                  // 1
+    pub maker_stake: u64,             // 8
+    pub taker_stake: u64,             // 8 (maker_stake * odds)
+    pub expiry: i64,                  // 8 (last moment to accept)
+    pub accepted: bool,               // 1
+}
+impl Offer {
+    pub const INIT_SPACE: usize = 8 + 1 + 32 + 32 + (1 + 32) + 1 + 8 + 8 + 8 + 1;
+
+    /// PDA signer seeds of the offer, which owns its vault.
+    pub fn signer_seeds<'a>(&'a self, id_bytes: &'a [u8; 8], bump: &'a [u8; 1]) -> [&'a [u8]; 4] {
+        [b"offer", self.market.as_ref(), id_bytes, bump]
+    }
+}
+
+/// Collateral that underwrites parlays in one token. `liabilities` is the most every open parlay
+/// can still pay, and stakes and funding beyond it are free to back new parlays.
+#[account]
+pub struct ParlayVault {
+    pub bump: u8,                     // 1
+    pub token_mint: Pubkey,           // 32
+    pub parlay_count: u64,            // 8 (parlays created so far, used as parlay id)
+    pub liabilities: u64,             // 8 (sum of open parlays' max payouts)
+}
+impl ParlayVault {
+    pub const INIT_SPACE: usize = 1 + 32 + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct ParlayLeg {
+    pub market: Pubkey,               // 32
+    pub outcome: u8,                  // 1
+    pub odds: u64,                    // 8 (decimal odds on PRECISION, locked in at creation)
+}
+impl ParlayLeg {
+    pub const SPACE: usize = 32 + 1 + 8;
+}
+
+/// Bet on several markets at once, paying `stake` times the product of the legs' odds if every
+/// leg resolves to its outcome. Void legs are dropped from the product.
+#[account]
+pub struct Parlay {
+    pub id: u64,                      // 8 (unique per vault)
+    pub bump: u8,                     // 1
+    pub vault: Pubkey,                // 32
+    pub user: Pubkey,                 // 32
+    pub stake: u64,                   // 8
+    pub max_payout: u64,              // 8 (payout if every leg wins, reserved in the vault)
+    pub legs: Vec<ParlayLeg>,         // 4 + MAX_PARLAY_LEGS * 41
+}
+impl Parlay {
+    pub const INIT_SPACE: usize = 8 + 1 + 32 + 32 + 8 + 8 + (4 + MAX_PARLAY_LEGS * ParlayLeg::SPACE);
+
+    /// `stake` times the odds of every leg `keep` selects.
+    pub fn payout(&self, keep: impl Fn(usize) -> bool) -> Result<u64> {
+        let mut payout = self.stake as u128;
+        for (_, leg) in self.legs.iter().enumerate().filter(|(i, _)| keep(*i)) {
+            payout = payout
+                .checked_mul(leg.odds as u128).ok_or(ErrorCode::AmountOverflow)?
+                .checked_div(PRECISION).ok_or(ErrorCode::AmountOverflow)?;
+        }
+        payout.try_into().map_err(|_| ErrorCode::AmountOverflow.into())
+    }
+}
+
+#[account]
+pub struct VoteTally {
+    pub market: Pubkey,                          // 32
+    pub bump: u8,                                // 1
+    pub votes: [Option<u8>; MAX_RESOLVERS],      // 7 * (1 + 1) (vote of each resolver, same order as the market's set)
+    pub tallies: [u8; MAX_OUTCOMES],             // 16 (votes per outcome)
+}
+
+impl VoteTally {
+    pub const INIT_SPACE: usize = 32 + 1 + MAX_RESOLVERS * 2 + MAX_OUTCOMES;
 }
 
 #[derive(Accounts)]
@@ -66,8 +1002,84 @@ pub struct InitializeProtocol<'info> {
 }
 
 #[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut, seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeRecipient<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeSplit<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateResolutionConfig<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolPaused<'info> {
+    #[account(mut, seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMarketPaused<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EnableEmergencyMode<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+    pub authority: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+#[instruction(question: String, outcomes: Vec<String>)]
 pub struct CreateMarket<'info> {
-    #[account(init, payer = creator, space = 8 + Market::INIT_SPACE, seeds = [b"market", protocol.market_count.to_le_bytes().as_ref()], bump)]
+    #[account(init, payer = creator, space = 8 + Market::space(outcomes.len()), seeds = [b"market", protocol.market_count.to_le_bytes().as_ref()], bump)]
     pub market: Account<'info, Market>,
     #[account(mut)]
     pub protocol: Account<'info, Protocol>,
@@ -83,6 +1095,10 @@ pub struct CreateMarket<'info> {
     )]
     pub market_escrow: Account<'info, TokenAccount>,
 
+    // Required when the market is created with seed liquidity
+    #[account(mut, token::mint = token_mint, token::authority = creator)]
+    pub creator_token_account: Option<Account<'info, TokenAccount>>,
+
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -95,7 +1111,7 @@ pub struct PlaceBet<'info> {
     #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
     pub market: Account<'info, Market>,
 
-    // New position PDA uses market.position_count as unique index
+    // New position PDA uses market.position_count as unique index (markets without outcome tokens)
     #[account(
         init,
         payer = user,
@@ -103,7 +1119,22 @@ pub struct PlaceBet<'info> {
         seeds = [b"position", user.key().as_ref(), market.key().as_ref(), market.position_count.to_le_bytes().as_ref()],
         bump
     )]
-    pub position: Account<'info, Position>,
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets mint the shares here instead of opening a position
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = outcome_mint,
+        associated_token::authority = user
+    )]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -114,8 +1145,18 @@ pub struct PlaceBet<'info> {
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
     pub market_escrow: Account<'info, TokenAccount>,
 
+    // Optional Core asset minted for the position (fresh keypair)
+    #[account(mut)]
+    pub position_asset: Option<Signer<'info>>,
+
+    /// CHECK: Metaplex Core program, required with position_asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -128,6 +1169,9 @@ pub struct IncreasePosition<'info> {
     #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
     pub position: Account<'info, Position>,
 
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
@@ -137,8 +1181,18 @@ pub struct IncreasePosition<'info> {
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
     pub market_escrow: Account<'info, TokenAccount>,
 
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -146,8 +1200,16 @@ pub struct WithdrawFromPosition<'info> {
     #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
     pub market: Account<'info, Market>,
 
+    // Markets without outcome tokens
     #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
-    pub position: Account<'info, Position>,
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets: the mint of the outcome held and the user's tokens of it
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut, token::mint = outcome_mint, token::authority = user)]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -158,7 +1220,6 @@ pub struct WithdrawFromPosition<'info> {
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
     pub market_escrow: Account<'info, TokenAccount>,
 
-    // protocol & dev token accounts (must exist)
     #[account(seeds = [b"protocol"], bump)]
     pub protocol: Account<'info, Protocol>,
 
@@ -166,14 +1227,25 @@ pub struct WithdrawFromPosition<'info> {
     #[account(mut)]
     pub authority_fee_recipient: AccountInfo<'info>,
 
+    // Optional when the corresponding share of the fee split is zero
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
-    pub protocol_token_account: Account<'info, TokenAccount>,
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
 
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
-    pub dev_token_account: Account<'info, TokenAccount>,
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
 
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -181,8 +1253,16 @@ pub struct CancelPosition<'info> {
     #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
     pub market: Account<'info, Market>,
 
+    // Markets without outcome tokens
     #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
-    pub position: Account<'info, Position>,
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets: the mint of the outcome held and the user's tokens of it
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut, token::mint = outcome_mint, token::authority = user)]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -200,12 +1280,22 @@ pub struct CancelPosition<'info> {
     #[account(mut)]
     pub authority_fee_recipient: AccountInfo<'info>,
 
+    // Optional when the corresponding share of the fee split is zero
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
-    pub protocol_token_account: Account<'info, TokenAccount>,
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
 
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
-    pub dev_token_account: Account<'info, TokenAccount>,
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
 
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
 }
@@ -214,19 +1304,308 @@ pub struct CancelPosition<'info> {
 pub struct ResolveMarket<'info> {
     #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
     pub market: Account<'info, Market>,
+
+    #[account(mut)]
     pub creator: Signer<'info>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut, token::mint = token_mint, token::authority = creator)]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
+    // holds the proposer and disputer bonds (market PDA is owner)
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"bond_vault", market.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = market
+    )]
+    pub bond_vault: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ClaimWinnings<'info> {
+pub struct DisputeResolution<'info> {
     #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
     pub market: Account<'info, Market>,
 
-    #[account(seeds = [b"protocol"], bump)]
-    pub protocol: Account<'info, Protocol>,
+    pub disputer: Signer<'info>,
 
+    #[account(mut, token::mint = token_mint, token::authority = disputer)]
+    pub disputer_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds = [b"bond_vault", market.key().as_ref()], bump)]
+    pub bond_vault: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeResolution<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"bond_vault", market.key().as_ref()], bump)]
+    pub bond_vault: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = token_mint)]
+    pub proposer_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ArbitrateDispute<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    pub arbiter: Signer<'info>,
+
+    #[account(mut, seeds = [b"bond_vault", market.key().as_ref()], bump)]
+    pub bond_vault: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = token_mint)]
+    pub winner_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveFromOracle<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: owner, layout and key are validated against the market's resolution source
+    pub oracle_account: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitResolutionVote<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = resolver,
+        space = 8 + VoteTally::INIT_SPACE,
+        seeds = [b"vote_tally", market.key().as_ref()],
+        bump
+    )]
+    pub vote_tally: Account<'info, VoteTally>,
+
+    #[account(mut)]
+    pub resolver: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VoidMarket<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VoidExpiredMarket<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    // Required when the market has a disputed proposal, to return both bonds
+    #[account(mut, seeds = [b"bond_vault", market.key().as_ref()], bump)]
+    pub bond_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, constraint = proposer_token_account.mint == market.token_mint @ ErrorCode::InvalidMint)]
+    pub proposer_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, constraint = disputer_token_account.mint == market.token_mint @ ErrorCode::InvalidMint)]
+    pub disputer_token_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinnings<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    // Markets without outcome tokens
     #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
-    pub position: Account<'info, Position>,
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets: the mint of the outcome held and the user's tokens of it
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut, token::mint = outcome_mint, token::authority = user)]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: The private account of the deployer
+    #[account(mut)]
+    pub authority_fee_recipient: AccountInfo<'info>,
+
+    // Optional when the corresponding share of the fee split is zero
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+
+    // Required for conditional markets, checked against market.parent
+    pub parent_market: Option<Account<'info, Market>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitOutcomeMints<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// remaining_accounts: `[outcome_mint, user_outcome_account]` for every outcome, in order
+#[derive(Accounts)]
+pub struct SplitCollateral<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+// remaining_accounts: `[outcome_mint, user_outcome_account]` for every outcome, in order
+#[derive(Accounts)]
+pub struct MergePositions<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"lp_mint", market.key().as_ref()],
+        bump,
+        mint::decimals = token_mint.decimals,
+        mint::authority = market
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = lp_mint,
+        associated_token::authority = user
+    )]
+    pub user_lp_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut, seeds = [b"lp_mint", market.key().as_ref()], bump)]
+    pub lp_mint: Account<'info, Mint>,
 
     #[account(mut)]
     pub user: Signer<'info>,
@@ -234,6 +1613,42 @@ pub struct ClaimWinnings<'info> {
     #[account(mut, token::mint = token_mint, token::authority = user)]
     pub user_token_account: Account<'info, TokenAccount>,
 
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: The private account of the deployer
+    #[account(mut)]
+    pub authority_fee_recipient: AccountInfo<'info>,
+
+    // Optional when the corresponding share of the fee split is zero
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimSeedLiquidity<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump, has_one = creator @ ErrorCode::Unauthorized)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = creator)]
+    pub creator_token_account: Account<'info, TokenAccount>,
+
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
     pub market_escrow: Account<'info, TokenAccount>,
 
@@ -241,12 +1656,996 @@ pub struct ClaimWinnings<'info> {
     #[account(mut)]
     pub authority_fee_recipient: AccountInfo<'info>,
 
+    // Optional when the corresponding share of the fee split is zero
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
-    pub protocol_token_account: Account<'info, TokenAccount>,
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
 
     #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
-    pub dev_token_account: Account<'info, TokenAccount>,
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Required for conditional markets, checked against market.parent
+    pub parent_market: Option<Account<'info, Market>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    // Markets without outcome tokens
+    #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets: the mint of the outcome held and the user's tokens of it
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut, token::mint = outcome_mint, token::authority = user)]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
 
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
 }
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    // Closed and re-created under the new owner's seeds (same position id)
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [b"position", new_owner.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub new_position: Account<'info, Position>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: Any wallet, multisig or program PDA can own a position
+    pub new_owner: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    // Markets without outcome tokens
+    #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
+    pub position: Option<Account<'info, Position>>,
+
+    // Outcome token markets: the mint of the outcome held and the user's tokens of it
+    #[account(mut)]
+    pub outcome_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut, token::mint = outcome_mint, token::authority = user)]
+    pub user_outcome_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: Core asset of the position, checked against position.asset
+    #[account(mut)]
+    pub position_asset: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Metaplex Core program, required when the position has an asset
+    #[account(address = asset::MPL_CORE_PROGRAM_ID)]
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBuyOrder<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Order::INIT_SPACE,
+        seeds = [b"order", market.key().as_ref(), market.order_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+
+    // holds the collateral until it is spent (order PDA is owner)
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"order_vault", order.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = order
+    )]
+    pub order_vault: Account<'info, TokenAccount>,
+
+    // Empty position receiving the shares as the order fills
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [b"position", user.key().as_ref(), market.key().as_ref(), market.position_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceSellOrder<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Order::INIT_SPACE,
+        seeds = [b"order", market.key().as_ref(), market.order_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order: Account<'info, Order>,
+
+    // receives the sale proceeds (order PDA is owner)
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"order_vault", order.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = order
+    )]
+    pub order_vault: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds = [b"position", position.user.key().as_ref(), market.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
+    pub position: Account<'info, Position>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FillOrder<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, has_one = market @ ErrorCode::InvalidOrder, has_one = position @ ErrorCode::InvalidOrder)]
+    pub order: Account<'info, Order>,
+
+    #[account(mut, seeds = [b"order_vault", order.key().as_ref()], bump)]
+    pub order_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub position: Account<'info, Position>,
+
+    // Opposite order to cross with instead of the pool, with its vault and position
+    #[account(mut, has_one = market @ ErrorCode::InvalidOrder)]
+    pub counter_order: Option<Account<'info, Order>>,
+
+    #[account(mut, token::mint = token_mint)]
+    pub counter_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub counter_position: Option<Account<'info, Position>>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    /// CHECK: The private account of the deployer
+    #[account(mut)]
+    pub authority_fee_recipient: AccountInfo<'info>,
+
+    // Optional when the corresponding share of the fee split is zero
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = authority_fee_recipient)]
+    pub protocol_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = protocol.dev_recipient)]
+    pub dev_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = market @ ErrorCode::InvalidOrder,
+        has_one = owner @ ErrorCode::InvalidOrder,
+        has_one = position @ ErrorCode::InvalidOrder
+    )]
+    pub order: Account<'info, Order>,
+
+    #[account(mut, seeds = [b"order_vault", order.key().as_ref()], bump)]
+    pub order_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub position: Account<'info, Position>,
+
+    /// CHECK: Order owner, receives the rent and the vault balance
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    // The owner, or anyone once the order can no longer fill
+    pub caller: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitOrderBook<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + OrderBook::INIT_SPACE,
+        seeds = [b"order_book", market.key().as_ref()],
+        bump
+    )]
+    pub order_book: Account<'info, OrderBook>,
+
+    // holds the collateral of resting orders and unsettled fills (market PDA is owner)
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"book_vault", market.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = market
+    )]
+    pub book_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceBookOrder<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"order_book", market.key().as_ref()], bump = order_book.bump)]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(mut, seeds = [b"book_vault", market.key().as_ref()], bump)]
+    pub book_vault: Account<'info, TokenAccount>,
+
+    // Empty position receiving the shares as the order fills
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Position::INIT_SPACE,
+        seeds = [b"position", user.key().as_ref(), market.key().as_ref(), market.position_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBookOrder<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"order_book", market.key().as_ref()], bump = order_book.bump)]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(mut, seeds = [b"book_vault", market.key().as_ref()], bump)]
+    pub book_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: Order owner, checked against the book
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    // The owner, or anyone once the market stops trading
+    pub caller: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ConsumeBookEvents<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, seeds = [b"order_book", market.key().as_ref()], bump = order_book.bump)]
+    pub order_book: Account<'info, OrderBook>,
+
+    #[account(mut, seeds = [b"book_vault", market.key().as_ref()], bump)]
+    pub book_vault: Account<'info, TokenAccount>,
+
+    #[account(mut, associated_token::mint = token_mint, associated_token::authority = market)]
+    pub market_escrow: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        init,
+        payer = maker,
+        space = 8 + Offer::INIT_SPACE,
+        seeds = [b"offer", market.key().as_ref(), market.offer_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub offer: Account<'info, Offer>,
+
+    // holds both stakes until settlement (offer PDA is owner)
+    #[account(
+        init,
+        payer = maker,
+        seeds = [b"offer_vault", offer.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = offer
+    )]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = maker)]
+    pub maker_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(mut, has_one = market @ ErrorCode::InvalidOffer)]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut, seeds = [b"offer_vault", offer.key().as_ref()], bump)]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    pub taker: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = taker)]
+    pub taker_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = maker,
+        has_one = market @ ErrorCode::InvalidOffer,
+        has_one = maker @ ErrorCode::InvalidOffer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut, seeds = [b"offer_vault", offer.key().as_ref()], bump)]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: Offer maker, receives the rent and the stake
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = maker)]
+    pub maker_token_account: Account<'info, TokenAccount>,
+
+    // The maker, or anyone once the offer can no longer be accepted
+    pub caller: Signer<'info>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleOffer<'info> {
+    #[account(seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        close = maker,
+        has_one = market @ ErrorCode::InvalidOffer,
+        has_one = maker @ ErrorCode::InvalidOffer
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(mut, seeds = [b"offer_vault", offer.key().as_ref()], bump)]
+    pub offer_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: Offer maker, receives the rent
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = maker)]
+    pub maker_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = token_mint, constraint = Some(taker_token_account.owner) == offer.taker @ ErrorCode::InvalidOffer)]
+    pub taker_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = market.token_mint @ ErrorCode::InvalidMint)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitParlayVault<'info> {
+    #[account(seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ParlayVault::INIT_SPACE,
+        seeds = [b"parlay_vault", token_mint.key().as_ref()],
+        bump
+    )]
+    pub parlay_vault: Account<'info, ParlayVault>,
+
+    // holds the underwriting capital and the stakes (parlay vault PDA is owner)
+    #[account(
+        init,
+        payer = authority,
+        seeds = [b"parlay_reserve", parlay_vault.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = parlay_vault
+    )]
+    pub reserve: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundParlayVault<'info> {
+    #[account(seeds = [b"parlay_vault", token_mint.key().as_ref()], bump = parlay_vault.bump)]
+    pub parlay_vault: Account<'info, ParlayVault>,
+
+    #[account(mut, seeds = [b"parlay_reserve", parlay_vault.key().as_ref()], bump)]
+    pub reserve: Account<'info, TokenAccount>,
+
+    pub funder: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = funder)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawParlayVault<'info> {
+    #[account(seeds = [b"protocol"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(seeds = [b"parlay_vault", token_mint.key().as_ref()], bump = parlay_vault.bump)]
+    pub parlay_vault: Account<'info, ParlayVault>,
+
+    #[account(mut, seeds = [b"parlay_reserve", parlay_vault.key().as_ref()], bump)]
+    pub reserve: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint)]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateParlay<'info> {
+    #[account(mut, seeds = [b"parlay_vault", token_mint.key().as_ref()], bump = parlay_vault.bump)]
+    pub parlay_vault: Account<'info, ParlayVault>,
+
+    #[account(mut, seeds = [b"parlay_reserve", parlay_vault.key().as_ref()], bump)]
+    pub reserve: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Parlay::INIT_SPACE,
+        seeds = [b"parlay", parlay_vault.key().as_ref(), parlay_vault.parlay_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub parlay: Account<'info, Parlay>,
+
+    #[account(seeds = [b"protocol"], bump)]
+    pub protocol: Account<'info, Protocol>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleParlay<'info> {
+    #[account(mut, seeds = [b"parlay_vault", token_mint.key().as_ref()], bump = parlay_vault.bump)]
+    pub parlay_vault: Account<'info, ParlayVault>,
+
+    #[account(mut, seeds = [b"parlay_reserve", parlay_vault.key().as_ref()], bump)]
+    pub reserve: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        close = user,
+        constraint = parlay.vault == parlay_vault.key() @ ErrorCode::InvalidParlay,
+        has_one = user @ ErrorCode::InvalidParlay
+    )]
+    pub parlay: Account<'info, Parlay>,
+
+    /// CHECK: Parlay owner, receives the payout and the rent
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = token_mint, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LinkParentMarket<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"market", parent_market.id.to_le_bytes().as_ref()], bump = parent_market.bump)]
+    pub parent_market: Account<'info, Market>,
+
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VoidConditionalMarket<'info> {
+    #[account(mut, seeds = [b"market", market.id.to_le_bytes().as_ref()], bump = market.bump)]
+    pub market: Account<'info, Market>,
+
+    #[account(seeds = [b"market", parent_market.id.to_le_bytes().as_ref()], bump = parent_market.bump)]
+    pub parent_market: Account<'info, Market>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(outcome_count: usize) -> Market {
+        Market {
+            id: 0,
+            bump: 0,
+            creator: Pubkey::new_unique(),
+            question: String::new(),
+            outcomes: vec![String::new(); outcome_count],
+            end_time: 0,
+            resolved: false,
+            winning_outcome: None,
+            total_volume: 0,
+            outcome_pools: vec![0; outcome_count],
+            position_count: 0,
+            paused: false,
+            emergency: false,
+            market_type: MarketType::Categorical,
+            resolved_value: None,
+            voided: false,
+            resolution_deadline: 0,
+            token_mint: Pubkey::new_unique(),
+            proposal: None,
+            resolution_source: ResolutionSource::Creator,
+            outcome_mint_count: 0,
+            lp_reserve: vec![0; outcome_count],
+            lp_fees: 0,
+            seed_shares: 0,
+            outcome_shares: vec![0; outcome_count],
+            pricing: PricingMode::ConstantProduct,
+            subsidy: 0,
+            order_count: 0,
+            pending_fills: 0,
+            offer_count: 0,
+            parent: None,
+            lp_shares: 0,
+            outcome_costs: vec![0; outcome_count],
+            price_snapshot: vec![0; outcome_count],
+            snapshot_time: 0,
+            parlay_legs: 0,
+        }
+    }
+
+    fn seeded(amount: u64, odds_bps: &[u16]) -> Market {
+        let mut market = market(odds_bps.len());
+        market.seed_pools(&SeedLiquidity { amount, odds_bps: odds_bps.to_vec() }).unwrap();
+        market
+    }
+
+    fn spot_prices(market: &Market) -> Vec<u64> {
+        (0..market.outcomes.len()).map(|i| market.price(i).unwrap()).collect()
+    }
+
+    fn assert_close(actual: u64, expected: u64, tolerance: u64) {
+        assert!(actual.abs_diff(expected) <= tolerance, "expected {expected}, got {actual}");
+    }
+
+    /// Every share of every outcome is either in a pool, held for the LPs or held by a trader.
+    fn assert_balanced(market: &Market) {
+        for i in 0..market.outcomes.len() {
+            let shares = market.outcome_pools[i] + market.lp_reserve[i] + market.outcome_shares[i];
+            assert_eq!(shares, market.total_volume, "outcome {i}");
+        }
+    }
+
+    #[test]
+    fn opens_at_seeded_prices() {
+        let market = seeded(1_000_000_000, &[6_000, 4_000]);
+        let prices = spot_prices(&market);
+        assert_close(prices[0], 600_000_000, 1);
+        assert_close(prices[1], 400_000_000, 1);
+        // The likeliest outcome has the smallest pool
+        assert!(market.outcome_pools[0] < market.outcome_pools[1]);
+        assert_balanced(&market);
+
+        let market = seeded(3_000_000_000, &[5_000, 3_000, 2_000]);
+        let prices = spot_prices(&market);
+        assert_close(prices[0], 500_000_000, 1);
+        assert_close(prices[1], 300_000_000, 1);
+        assert_close(prices[2], 200_000_000, 1);
+        assert_eq!(market.lp_shares, 3_000_000_000);
+        assert_balanced(&market);
+    }
+
+    #[test]
+    fn buys_move_prices_toward_the_outcome() {
+        let mut market = seeded(1_000_000_000, &[5_000, 3_000, 2_000]);
+        let before = spot_prices(&market);
+
+        let quote = market.buy_quote(1, 100_000_000, 100).unwrap();
+        assert_eq!(quote.fee, 1_000_000);
+        // Bought below one unit per share, above the opening price
+        assert!(quote.shares > 99_000_000 && quote.shares < 99_000_000 * 10 / 3);
+        market.apply_buy(1, 100_000_000, &quote).unwrap();
+
+        let after = spot_prices(&market);
+        assert!(after[1] > before[1]);
+        assert!(after[0] < before[0] && after[2] < before[2]);
+        assert_close(after.iter().sum(), PRECISION as u64, 3);
+        assert_eq!(market.lp_fees, 1_000_000);
+        assert_eq!(market.outcome_costs[1], 100_000_000);
+        assert_balanced(&market);
+    }
+
+    #[test]
+    fn sells_never_return_more_than_was_paid() {
+        let mut market = seeded(1_000_000_000, &[6_000, 4_000]);
+        let quote = market.buy_quote(0, 250_000_000, 0).unwrap();
+        market.apply_buy(0, 250_000_000, &quote).unwrap();
+
+        let sale = market.sell_quote(0, quote.shares, 0, 0).unwrap();
+        assert!(sale.payout_gross <= 250_000_000);
+        assert_close(sale.payout_gross, 250_000_000, 10);
+        market.apply_sell(0, quote.shares, 250_000_000, &sale).unwrap();
+
+        let prices = spot_prices(&market);
+        assert_close(prices[0], 600_000_000, 10);
+        assert_eq!(market.outcome_shares[0], 0);
+        assert_balanced(&market);
+    }
+
+    #[test]
+    fn liquidity_keeps_prices() {
+        let mut market = seeded(1_000_000_000, &[7_000, 3_000]);
+        let quote = market.buy_quote(1, 200_000_000, 0).unwrap();
+        market.apply_buy(1, 200_000_000, &quote).unwrap();
+        let before = spot_prices(&market);
+
+        let units = market.add_liquidity(500_000_000).unwrap();
+        assert!(units > 0);
+        let after = spot_prices(&market);
+        assert_close(after[0], before[0], 2);
+        assert_close(after[1], before[1], 2);
+        assert_balanced(&market);
+
+        // The units are worth the deposit at the current prices
+        let worth = units as u128 * market.liquidity_value().unwrap() as u128 / market.lp_shares as u128;
+        assert_close(worth as u64, 500_000_000, 5);
+
+        // Leaving right away sells the unbalanced shares on the curve, never for more than was paid,
+        // and the sale pays the AMM fee to the remaining liquidity and the cancel fee to the protocol
+        let (shares, _) = market.take_liquidity(units).unwrap();
+        let (value, protocol_fee) = market.exit_liquidity(&shares, 30, 200).unwrap();
+        assert!(protocol_fee > 0 && market.lp_fees > 0);
+        assert!(value + protocol_fee + market.lp_fees <= 500_000_000);
+        assert_balanced(&market);
+    }
+
+    #[test]
+    fn complete_sets_are_trader_shares() {
+        let mut market = seeded(1_000_000_000, &[5_000, 5_000]);
+        let before = spot_prices(&market);
+        market.split_sets(301).unwrap();
+        assert_eq!(market.outcome_shares, vec![301, 301]);
+        assert_eq!(market.outcome_costs, vec![151, 150]);
+        assert_eq!(spot_prices(&market), before);
+        assert_balanced(&market);
+
+        // One side of the sets can be sold to the pools, and the rest merged
+        let cost = market.average_cost(0, 100).unwrap();
+        let quote = market.sell_quote(0, 100, 30, 200).unwrap();
+        market.apply_sell(0, 100, cost, &quote).unwrap();
+        assert_balanced(&market);
+        // All that is left of outcome 0 and its cost, and 201 / 301 of outcome 1 at its average cost
+        assert_eq!(market.merge_sets(201).unwrap(), 101 + 100);
+        assert!(market.merge_sets(1).is_err());
+        assert_eq!(market.outcome_costs, vec![0, 50]);
+        assert_balanced(&market);
+    }
+
+    #[test]
+    fn lmsr_surplus_leaves_winners_paid() {
+        let b = 1_000_000_000;
+        let mut market = market(2);
+        market.pricing = PricingMode::Lmsr { b };
+        market.subsidy = lmsr::max_loss(b, 2).unwrap();
+        for (idx, amount) in [(0, 900_000_000), (1, 100_000_000)] {
+            let quote = market.buy_quote(idx, amount, 30).unwrap();
+            market.apply_buy(idx, amount, &quote).unwrap();
+        }
+        market.resolved = true;
+
+        // Only the winning outcome's shares are owed, whichever side wins
+        for winner in [0usize, 1] {
+            market.winning_outcome = Some(winner as u8);
+            let funds = market.total_volume + market.subsidy;
+            assert_eq!(market.lmsr_surplus().unwrap(), funds - market.outcome_shares[winner]);
+        }
+
+        market.resolved = false;
+        market.winning_outcome = None;
+        assert!(market.lmsr_surplus().is_err());
+    }
+
+    #[test]
+    fn pays_fixed_values_per_share() {
+        let mut market = seeded(1_000_000_000, &[5_000, 5_000]);
+        market.resolved = true;
+        market.winning_outcome = Some(1);
+        assert_eq!(market.payout_value(1, 300).unwrap(), 300);
+        assert_eq!(market.payout_value(0, 300).unwrap(), 0);
+        assert!(market.winnings(0, 300).is_err());
+
+        market.market_type = MarketType::Scalar { lower_bound: 0, upper_bound: 100 };
+        market.resolved_value = Some(25);
+        assert_eq!(market.payout_value(0, 400).unwrap(), 100);
+        assert_eq!(market.payout_value(1, 400).unwrap(), 300);
+    }
+
+    #[test]
+    fn disputed_markets_void_one_window_late() {
+        let mut market = market(2);
+        market.resolution_deadline = 1_000;
+        assert_eq!(market.void_deadline(100).unwrap(), 1_000);
+
+        let mut proposal = ResolutionProposal {
+            proposer: Pubkey::new_unique(),
+            outcome: Some(0),
+            value: None,
+            bond: 10,
+            dispute_deadline: 900,
+            disputer: None,
+        };
+        market.proposal = Some(proposal);
+        assert!(market.void_deadline(100).is_err());
+
+        proposal.disputer = Some(Pubkey::new_unique());
+        market.proposal = Some(proposal);
+        assert_eq!(market.void_deadline(100).unwrap(), 1_100);
+
+        proposal.dispute_deadline = 1_050;
+        market.proposal = Some(proposal);
+        assert_eq!(market.void_deadline(100).unwrap(), 1_150);
+    }
+
+    #[test]
+    fn parlay_legs_price_on_the_snapshot() {
+        let mut market = seeded(1_000_000_000, &[5_000, 5_000]);
+        assert_eq!(market.leg_odds(0, 0).unwrap(), 2 * PRECISION as u64);
+        assert_eq!(market.leg_odds(0, 100).unwrap(), 2 * PRECISION as u64 * 99 / 100);
+
+        // The first trade snapshots the prices it starts from, later ones within the interval do not
+        market.record_prices(1_000).unwrap();
+        let quote = market.buy_quote(1, 20_000_000, 0).unwrap();
+        market.apply_buy(1, 20_000_000, &quote).unwrap();
+        market.record_prices(1_000 + PRICE_SNAPSHOT_INTERVAL - 1).unwrap();
+        assert_eq!(market.snapshot_time, 1_000);
+
+        // Outcome 0 got cheaper, its odds stay at the snapshot; outcome 1 pays at its new price
+        assert!(market.price(0).unwrap() < PRECISION as u64 / 2);
+        assert_eq!(market.leg_odds(0, 0).unwrap(), 2 * PRECISION as u64);
+        let spot = market.price(1).unwrap() as u128;
+        assert_eq!(market.leg_odds(1, 0).unwrap(), (PRECISION * PRECISION / spot) as u64);
+
+        // Moving the pools further fails until the next snapshot
+        let quote = market.buy_quote(1, 200_000_000, 0).unwrap();
+        market.apply_buy(1, 200_000_000, &quote).unwrap();
+        assert!(market.leg_odds(0, 0).is_err());
+        market.record_prices(1_000 + PRICE_SNAPSHOT_INTERVAL).unwrap();
+        assert!(market.leg_odds(0, 0).unwrap() > 2 * PRECISION as u64);
+
+        let long_shot = seeded(1_000_000_000, &[100, 9_900]);
+        assert_eq!(long_shot.leg_odds(0, 0).unwrap(), MAX_PARLAY_LEG_ODDS);
+    }
+
+    #[test]
+    fn short_escrow_refunds_pro_rata() {
+        // Seeded 100 at even odds, no fees. Each first buyer sells at a profit once the second has
+        // pushed the price up, leaving 1300 of recorded cost
+        let mut market = seeded(100, &[5_000, 5_000]);
+        let mut escrow = 100u64;
+        let buy = |market: &mut Market, idx: usize, amount: u64| {
+            let quote = market.buy_quote(idx, amount, 0).unwrap();
+            market.apply_buy(idx, amount, &quote).unwrap();
+            quote.shares
+        };
+        let first_yes = buy(&mut market, 0, 100);
+        buy(&mut market, 0, 300);
+        let sale = market.sell_quote(0, first_yes, 0, 0).unwrap();
+        market.apply_sell(0, first_yes, 100, &sale).unwrap();
+        let first_no = buy(&mut market, 1, 100);
+        buy(&mut market, 1, 1_000);
+        let sale_no = market.sell_quote(1, first_no, 0, 0).unwrap();
+        market.apply_sell(1, first_no, 100, &sale_no).unwrap();
+        escrow += 100 + 300 + 100 + 1_000;
+        escrow -= sale.payout_net + sale_no.payout_net;
+        assert_balanced(&market);
+        assert_eq!(market.outstanding_cost().unwrap(), 1_300);
+        assert!(escrow < 1_300, "escrow {escrow}");
+
+        // The YES and NO holders left claim in either order and get the same fraction of their cost
+        market.resolved = true;
+        market.voided = true;
+        let holders = [(0usize, market.outcome_shares[0], 300u64), (1, market.outcome_shares[1], 1_000)];
+        let expected: Vec<u64> = holders.iter().map(|(_, _, cost)| cost * escrow / 1_300).collect();
+        assert!(market.residual_escrow(escrow).is_ok());
+        for (i, (idx, shares, cost)) in holders.iter().enumerate().rev() {
+            let refund = market.refund_value(*cost, escrow).unwrap();
+            assert_close(refund, expected[i], 1);
+            market.release_shares(*idx, *shares, *cost).unwrap();
+            escrow -= refund;
+        }
+
+        // Nothing is left to the LPs but their fees, and they can still exit
+        assert_eq!(market.residual_escrow(escrow).unwrap(), 0);
+        let (_, fees) = market.take_liquidity(market.lp_shares).unwrap();
+        assert!(fees <= escrow);
+    }
+}
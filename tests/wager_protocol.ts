@@ -29,9 +29,37 @@ describe("wager_protocol", () => {
     let authorityFeeAccount: PublicKey;
     let devTokenAccount: PublicKey;
     let marketId: number;
+    let tokenMarketPda: PublicKey;
+    let outcomeMints: PublicKey[];
 
     const authorityFeeRecipient = provider.wallet.publicKey;
     const devRecipient = new PublicKey("8Nq7eMbvhZiPzZFeYutAoiHqF2uJTZZWwnBRzvkiUUid");
+    const MPL_CORE_PROGRAM_ID = new PublicKey("CoREENxT6tW1HoK8ypY1SxRMZTcVPm7R94rH4PZNhX7d");
+    // Constant product markets only take bets against seeded liquidity
+    const evenSeed = { amount: new anchor.BN(10_000000), oddsBps: [5000, 5000] };
+
+    // Unix time on the validator, and a wait until it reaches `timestamp`
+    const clockNow = async () => {
+        const slot = await provider.connection.getSlot();
+        return (await provider.connection.getBlockTime(slot)) as number;
+    };
+    const waitUntil = async (timestamp: number) => {
+        while ((await clockNow()) < timestamp) {
+            await new Promise((resolve) => setTimeout(resolve, 1000));
+        }
+    };
+
+    // A fresh wallet with `amount` of USDC and some SOL for account rent
+    const fundedWallet = async (amount: number) => {
+        const wallet = Keypair.generate();
+        const tokenAccount = await getAssociatedTokenAddress(usdcMint.publicKey, wallet.publicKey);
+        await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+            SystemProgram.transfer({ fromPubkey: provider.wallet.publicKey, toPubkey: wallet.publicKey, lamports: 100_000_000 }),
+            createAssociatedTokenAccountInstruction(provider.wallet.publicKey, tokenAccount, wallet.publicKey, usdcMint.publicKey),
+            createMintToInstruction(usdcMint.publicKey, tokenAccount, provider.wallet.publicKey, amount)
+        ));
+        return { wallet, tokenAccount };
+    };
 
     before(async () => {
         // Create USDC mock mint
@@ -76,12 +104,12 @@ describe("wager_protocol", () => {
                 usdcMint.publicKey,
                 userTokenAccount,
                 provider.wallet.publicKey,
-                1000_000000
+                10_000_000000
             )
         );
         await provider.sendAndConfirm(mintToTx);
 
-        console.log("✅ Setup complete. User has 1000 USDC");
+        console.log("✅ Setup complete. User has 10,000 USDC");
     });
 
     it("Initializes the protocol", async () => {
@@ -91,7 +119,7 @@ describe("wager_protocol", () => {
         );
 
         await program.methods
-            .initializeProtocol(500, 200, 30, authorityFeeRecipient)
+            .initializeProtocol(500, 200, 30, authorityFeeRecipient, devRecipient, 5000)
             .accounts({
                 authority: provider.wallet.publicKey,
             })
@@ -104,10 +132,179 @@ describe("wager_protocol", () => {
         assert.equal(protocol.cancelFeeBps, 200);
         assert.equal(protocol.ammFee, 30);
         assert.equal(protocol.marketCount.toNumber(), 0);
+        assert.ok(protocol.devRecipient.equals(devRecipient));
+        assert.equal(protocol.devFeeShareBps, 5000);
 
         console.log("✅ Protocol initialized");
     });
 
+    it("Updates the protocol fees", async () => {
+        await program.methods
+            .updateProtocolConfig(400, null, 25)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        let protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.protocolFeeBps, 400);
+        assert.equal(protocol.cancelFeeBps, 200);
+        assert.equal(protocol.ammFee, 25);
+
+        // amm_fee + cancel_fee_bps must stay below 10_000
+        try {
+            await program.methods
+                .updateProtocolConfig(null, 5000, null)
+                .accounts({
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected FeeTooHigh");
+        } catch (e) {
+            assert.include(e.toString(), "FeeTooHigh");
+        }
+
+        // Restore the original configuration
+        await program.methods
+            .updateProtocolConfig(500, 200, 30)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.protocolFeeBps, 500);
+        assert.equal(protocol.ammFee, 30);
+
+        console.log("✅ Protocol fees updated");
+    });
+
+    it("Transfers the protocol authority in two steps", async () => {
+        const newAuthority = Keypair.generate();
+
+        await program.methods
+            .proposeAuthority(newAuthority.publicKey)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        let protocol = await program.account.protocol.fetch(protocolPda);
+        assert.ok(protocol.pendingAuthority.equals(newAuthority.publicKey));
+        assert.ok(protocol.authority.equals(provider.wallet.publicKey));
+
+        await program.methods
+            .acceptAuthority()
+            .accounts({
+                newAuthority: newAuthority.publicKey,
+            })
+            .signers([newAuthority])
+            .rpc();
+
+        protocol = await program.account.protocol.fetch(protocolPda);
+        assert.ok(protocol.authority.equals(newAuthority.publicKey));
+        assert.equal(protocol.pendingAuthority, null);
+
+        // Hand the authority back for the remaining tests
+        await program.methods
+            .proposeAuthority(provider.wallet.publicKey)
+            .accounts({
+                authority: newAuthority.publicKey,
+            })
+            .signers([newAuthority])
+            .rpc();
+        await program.methods
+            .acceptAuthority()
+            .accounts({
+                newAuthority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        protocol = await program.account.protocol.fetch(protocolPda);
+        assert.ok(protocol.authority.equals(provider.wallet.publicKey));
+
+        console.log("✅ Authority transferred and returned");
+    });
+
+    it("Updates the dev fee split", async () => {
+        await program.methods
+            .updateFeeSplit(null, 2500)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        let protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.devFeeShareBps, 2500);
+        assert.ok(protocol.devRecipient.equals(devRecipient));
+
+        try {
+            await program.methods
+                .updateFeeSplit(null, 10001)
+                .accounts({
+                    authority: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected InvalidFeeShare");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidFeeShare");
+        }
+
+        await program.methods
+            .updateFeeSplit(null, 5000)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.devFeeShareBps, 5000);
+
+        console.log("✅ Fee split updated");
+    });
+
+    it("Configures the dispute window and bond", async () => {
+        // Short window so the resolution test can finalize
+        await program.methods
+            .updateResolutionConfig(new anchor.BN(1), new anchor.BN(0), null)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.disputeWindow.toNumber(), 1);
+        assert.equal(protocol.disputeBond.toNumber(), 0);
+
+        console.log("✅ Resolution config updated");
+    });
+
+    it("Rotates the authority fee recipient", async () => {
+        const newRecipient = Keypair.generate().publicKey;
+
+        await program.methods
+            .updateFeeRecipient(newRecipient)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        let protocol = await program.account.protocol.fetch(protocolPda);
+        assert.ok(protocol.authorityFeeRecipient.equals(newRecipient));
+
+        await program.methods
+            .updateFeeRecipient(authorityFeeRecipient)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        protocol = await program.account.protocol.fetch(protocolPda);
+        assert.ok(protocol.authorityFeeRecipient.equals(authorityFeeRecipient));
+
+        console.log("✅ Fee recipient rotated");
+    });
+
     it("Creates a market", async () => {
         const protocol = await program.account.protocol.fetch(protocolPda);
         marketId = protocol.marketCount.toNumber();
@@ -127,10 +324,11 @@ describe("wager_protocol", () => {
         const endTime = new anchor.BN(now + 86400);
 
         await program.methods
-            .createMarket("Will ETH hit $5000 by end of year?", ["Yes", "No"], endTime)
+            .createMarket("Will ETH hit $5000 by end of year?", ["Yes", "No"], endTime, endTime.addn(86400 * 7), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
             .accounts({
                 protocol: protocolPda,
                 creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
                 tokenMint: usdcMint.publicKey,
             })
             .rpc();
@@ -167,7 +365,7 @@ describe("wager_protocol", () => {
         ).value.amount;
 
         await program.methods
-            .placeBet(outcome, betAmount)
+            .placeBet(outcome, betAmount, new anchor.BN(0))
             .accounts({
                 user: provider.wallet.publicKey,
                 userTokenAccount: userTokenAccount,
@@ -183,8 +381,11 @@ describe("wager_protocol", () => {
 
         assert.ok(position.user.equals(provider.wallet.publicKey));
         assert.equal(position.outcome, 0);
-        assert.equal(position.amount.toNumber(), betAmount.toNumber());
-        assert.equal(marketAfter.totalVolume.toNumber(), betAmount.toNumber());
+        // 0.3% AMM fee, then 99.7 USDC against pools of 10/10: 10 * 10 / (10 + 99.7) rounded up stays in the pool
+        assert.equal(position.amount.toNumber(), 108_788422);
+        assert.equal(position.cost.toNumber(), betAmount.toNumber());
+        assert.equal(marketAfter.totalVolume.toNumber(), 109_700000);
+        assert.equal(marketAfter.lpFees.toNumber(), 300000);
         assert.equal(
             BigInt(userBalanceBefore) - BigInt(userBalanceAfter),
             BigInt(betAmount.toNumber())
@@ -193,7 +394,43 @@ describe("wager_protocol", () => {
         console.log("✅ Bet placed: 100 USDC on outcome 0");
     });
 
-    it("Places opposing bet to create liquidity", async () => {
+    it("Rejects bets while the protocol is paused", async () => {
+        await program.methods
+            .setProtocolPaused(true)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        try {
+            await program.methods
+                .placeBet(0, new anchor.BN(1_000000), new anchor.BN(0))
+                .accounts({
+                    market: marketPda,
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected ProtocolPaused");
+        } catch (e) {
+            assert.include(e.toString(), "ProtocolPaused");
+        }
+
+        await program.methods
+            .setProtocolPaused(false)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        assert.equal(protocol.paused, false);
+
+        console.log("✅ Pause switch enforced");
+    });
+
+    it("Places an opposing bet", async () => {
         const market = await program.account.market.fetch(marketPda);
         const positionId = market.positionCount.toNumber();
 
@@ -210,7 +447,7 @@ describe("wager_protocol", () => {
         const betAmount = new anchor.BN(100_000000);
 
         await program.methods
-            .placeBet(1, betAmount)
+            .placeBet(1, betAmount, new anchor.BN(0))
             .accounts({
                 user: provider.wallet.publicKey,
                 userTokenAccount: userTokenAccount,
@@ -221,21 +458,2269 @@ describe("wager_protocol", () => {
         console.log("✅ Opposing bet placed: 100 USDC on outcome 1");
     });
 
-    it("Resolves the market", async () => {
-        // Wait for end time or skip time check in test
-        const winningOutcome = 0;
+    it("Refunds stakes from a market in emergency mode", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const emergencyMarketId = protocol.marketCount.toNumber();
 
-        // For testing, you may need to modify end_time or wait
-        // Here we assume time check passes or is mocked
+        const [emergencyMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(emergencyMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
 
+        const now = Math.floor(Date.now() / 1000);
         await program.methods
-            .resolveMarket(winningOutcome)
+            .createMarket("Emergency market", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
             .accounts({
+                protocol: protocolPda,
                 creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
             })
             .rpc();
 
-        const market = await program.account.market.fetch(marketPda);
+        const [emergencyPositionPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                emergencyMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        );
+
+        const betAmount = new anchor.BN(10_000000);
+        await program.methods
+            .placeBet(1, betAmount, new anchor.BN(0))
+            .accounts({
+                market: emergencyMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .enableEmergencyMode()
+            .accounts({
+                market: emergencyMarketPda,
+                authority: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceBefore = (
+            await provider.connection.getTokenAccountBalance(userTokenAccount)
+        ).value.amount;
+
+        await program.methods
+            .emergencyWithdraw()
+            .accounts({
+                market: emergencyMarketPda,
+                position: emergencyPositionPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceAfter = (
+            await provider.connection.getTokenAccountBalance(userTokenAccount)
+        ).value.amount;
+
+        const position = await program.account.position.fetch(emergencyPositionPda);
+        assert.equal(position.claimed, true);
+        assert.equal(
+            BigInt(userBalanceAfter) - BigInt(userBalanceBefore),
+            BigInt(betAmount.toNumber())
+        );
+
+        console.log("✅ Emergency refund: 10 USDC returned");
+    });
+
+    it("Creates a categorical market with three outcomes", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const categoricalMarketId = protocol.marketCount.toNumber();
+
+        const [categoricalMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(categoricalMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Who wins the cup?", ["Team A", "Team B", "Team C"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, { amount: new anchor.BN(10_000000), oddsBps: [3400, 3300, 3300] }, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .placeBet(2, new anchor.BN(5_000000), new anchor.BN(0))
+            .accounts({
+                market: categoricalMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const market = await program.account.market.fetch(categoricalMarketPda);
+        assert.equal(market.outcomes.length, 3);
+        assert.equal(market.outcomePools.length, 3);
+        // The seed opens the pools at 34/33/33 and the bet is booked at what it paid
+        assert.deepEqual(market.outcomePools.slice(0, 2).map((p) => p.toNumber()), [14_690882, 14_985000]);
+        assert.equal(market.outcomeShares[2].toNumber(), 10_576098);
+        assert.equal(market.outcomeCosts[2].toNumber(), 5_000000);
+
+        try {
+            await program.methods
+                .placeBet(3, new anchor.BN(1_000000), new anchor.BN(0))
+                .accounts({
+                    market: categoricalMarketPda,
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected InvalidOutcome");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidOutcome");
+        }
+
+        console.log("✅ Categorical market created with 3 outcomes");
+    });
+
+    it("Creates a scalar market", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const scalarMarketId = protocol.marketCount.toNumber();
+
+        const [scalarMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(scalarMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        const scalar = { scalar: { lowerBound: new anchor.BN(100), upperBound: new anchor.BN(200) } };
+
+        await program.methods
+            .createMarket("SOL price on Dec 31?", ["Long", "Short"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), scalar, { creator: {} }, null, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const market = await program.account.market.fetch(scalarMarketPda);
+        assert.ok(market.marketType.scalar);
+        assert.equal(market.marketType.scalar.lowerBound.toNumber(), 100);
+        assert.equal(market.marketType.scalar.upperBound.toNumber(), 200);
+        assert.equal(market.resolvedValue, null);
+
+        // Scalar markets are settled with resolveScalarMarket, not resolveMarket
+        try {
+            await program.methods
+                .resolveMarket(0)
+                .accounts({
+                    market: scalarMarketPda,
+                    creator: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected resolveMarket to fail");
+        } catch (e) {
+            assert.notInclude(e.toString(), "Expected resolveMarket to fail");
+        }
+
+        // Two more markets on the range that end in a few seconds, with a LONG and a SHORT position
+        // each: one resolves inside the range, the other above it
+        const { wallet: trader, tokenAccount: traderTokenAccount } = await fundedWallet(20_000000);
+        const endTime = (await clockNow()) + 5;
+        const resolved: { market: PublicKey, value: number, positions: PublicKey[] }[] = [];
+        for (const value of [125, 250]) {
+            const { marketCount } = await program.account.protocol.fetch(protocolPda);
+            const [market] = PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            await program.methods
+                .createMarket("SOL price at the close?", ["Long", "Short"], new anchor.BN(endTime), new anchor.BN(endTime + 86400 * 7), scalar, { creator: {} }, evenSeed, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    creatorTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+
+            const positions: PublicKey[] = [];
+            for (const outcome of [0, 1]) {
+                await program.methods
+                    .placeBet(outcome, new anchor.BN(2_000000), new anchor.BN(0))
+                    .accounts({
+                        market,
+                        user: trader.publicKey,
+                        userTokenAccount: traderTokenAccount,
+                        tokenMint: usdcMint.publicKey,
+                    })
+                    .signers([trader])
+                    .rpc();
+                positions.push(PublicKey.findProgramAddressSync(
+                    [Buffer.from("position"), trader.publicKey.toBuffer(), market.toBuffer(), new anchor.BN(positions.length).toArrayLike(Buffer, "le", 8)],
+                    program.programId
+                )[0]);
+            }
+            resolved.push({ market, value, positions });
+        }
+
+        await waitUntil(endTime);
+        for (const { market, value } of resolved) {
+            await program.methods
+                .resolveScalarMarket(new anchor.BN(value))
+                .accounts({
+                    market,
+                    creator: provider.wallet.publicKey,
+                    creatorTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+        }
+        // Past the 1 second dispute window
+        await waitUntil((await clockNow()) + 2);
+        for (const { market } of resolved) {
+            await program.methods
+                .finalizeResolution()
+                .accounts({
+                    market,
+                    proposerTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+        }
+
+        // The authority fee recipient is the wallet, so its ATA is userTokenAccount
+        const devAta = await getAssociatedTokenAddress(usdcMint.publicKey, devRecipient, true);
+        try {
+            await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+                createAssociatedTokenAccountInstruction(provider.wallet.publicKey, devAta, devRecipient, usdcMint.publicKey)
+            ));
+        } catch (e) {
+            // Account may already exist
+        }
+
+        const { protocolFeeBps } = await program.account.protocol.fetch(protocolPda);
+        const claim = async (market: PublicKey, position: PublicKey) => {
+            const before = BigInt((await provider.connection.getTokenAccountBalance(traderTokenAccount)).value.amount);
+            await program.methods
+                .claimWinnings()
+                .accounts({
+                    market,
+                    position,
+                    user: trader.publicKey,
+                    userTokenAccount: traderTokenAccount,
+                    authorityFeeRecipient: authorityFeeRecipient,
+                    protocolTokenAccount: userTokenAccount,
+                    devTokenAccount: devAta,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .signers([trader])
+                .rpc();
+            const after = BigInt((await provider.connection.getTokenAccountBalance(traderTokenAccount)).value.amount);
+            return after - before;
+        };
+        const net = (gross: bigint) => gross - gross * BigInt(protocolFeeBps) / BigInt(10000);
+        const shares = async (position: PublicKey) =>
+            BigInt((await program.account.position.fetch(position)).amount.toString());
+
+        // 125 is a quarter of the way up the range: LONG pays 0.25 a share and SHORT 0.75
+        const [inside, above] = resolved;
+        const [insideLong, insideShort] = await Promise.all(inside.positions.map(shares));
+        assert.equal((await program.account.market.fetch(inside.market)).resolvedValue.toNumber(), 125);
+        assert.equal(await claim(inside.market, inside.positions[0]), net(insideLong * BigInt(25) / BigInt(100)));
+        assert.equal(await claim(inside.market, inside.positions[1]), net(insideShort * BigInt(75) / BigInt(100)));
+
+        // 250 is clamped to the top of the range: LONG pays a full unit and SHORT nothing
+        const aboveLong = await shares(above.positions[0]);
+        assert.equal(await claim(above.market, above.positions[0]), net(aboveLong));
+        try {
+            await claim(above.market, above.positions[1]);
+            assert.fail("Expected claimWinnings to fail");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidOutcome");
+        }
+
+        console.log("✅ Scalar markets created on range [100, 200] and settled inside and above it");
+    });
+
+    it("Creates a market resolved by a resolver committee", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const committeeMarketId = protocol.marketCount.toNumber();
+
+        const [committeeMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(committeeMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const members = [provider.wallet.publicKey, Keypair.generate().publicKey, Keypair.generate().publicKey];
+        const resolvers = [...members, ...Array(4).fill(PublicKey.default)];
+        const now = Math.floor(Date.now() / 1000);
+
+        // A threshold above the committee size is rejected
+        try {
+            await program.methods
+                .createMarket("Who wins the final?", ["Home", "Away"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { committee: { resolvers, resolverCount: 3, threshold: 4 } }, null, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected createMarket to fail");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidResolverSet");
+        }
+
+        await program.methods
+            .createMarket("Who wins the final?", ["Home", "Away"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { committee: { resolvers, resolverCount: 3, threshold: 2 } }, null, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const market = await program.account.market.fetch(committeeMarketPda);
+        assert.equal(market.resolutionSource.committee.resolverCount, 3);
+        assert.equal(market.resolutionSource.committee.threshold, 2);
+        assert.ok(market.resolutionSource.committee.resolvers[1].equals(members[1]));
+
+        // Votes only open once the market has ended
+        try {
+            await program.methods
+                .submitResolutionVote(0)
+                .accounts({
+                    market: committeeMarketPda,
+                    resolver: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected submitResolutionVote to fail");
+        } catch (e) {
+            assert.include(e.toString(), "MarketNotEnded");
+        }
+
+        console.log("✅ Committee market created with a 2-of-3 resolver set");
+    });
+
+    it("Resolves Pyth markets from fresh prices and rejects stale ones", async () => {
+        // Preloaded by Anchor.toml: SOL/USD at 150 (exponent -8), published at these fixed times
+        const freshPrice = new PublicKey("5TZhjguubdMLtEN3utXrTLa5grptYcCCjtj199Ngn59i");
+        const stalePrice = new PublicKey("2BtmUauHoZ25PcrcvcQvpUKJgrYr2pVdF3eTBUL3ucZQ");
+        const freshPublishTime = 4_000_000_000;
+        const feedId = Array.from(Buffer.from("ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d", "hex"));
+
+        // The markets end in a few seconds and accept prices up to the fresh publish time
+        const endTime = (await clockNow()) + 5;
+        const source = (priceAccount: PublicKey) => ({
+            pyth: {
+                priceAccount,
+                feedId,
+                threshold: { value: new anchor.BN(100), exponent: 0, comparison: { above: {} } },
+                maxStaleness: new anchor.BN(freshPublishTime - endTime),
+                maxConfidenceBps: 100,
+            },
+        });
+
+        const markets: PublicKey[] = [];
+        for (const priceAccount of [freshPrice, stalePrice]) {
+            const protocol = await program.account.protocol.fetch(protocolPda);
+            const [oracleMarketPda] = PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), protocol.marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            await program.methods
+                .createMarket("Will SOL close above $100?", ["Yes", "No"], new anchor.BN(endTime), new anchor.BN(endTime + 86400 * 7), { categorical: {} }, source(priceAccount), null, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            markets.push(oracleMarketPda);
+        }
+        const [freshMarket, staleMarket] = markets;
+
+        await waitUntil(endTime);
+
+        const resolveFrom = (market: PublicKey, oracleAccount: PublicKey) =>
+            program.methods.resolveFromOracle().accounts({ market, oracleAccount }).rpc();
+
+        // Published an hour after the staleness window closed
+        try {
+            await resolveFrom(staleMarket, stalePrice);
+            assert.fail("Expected OraclePriceStale");
+        } catch (e) {
+            assert.include(e.toString(), "OraclePriceStale");
+        }
+
+        // Only the market's own price account is accepted
+        try {
+            await resolveFrom(freshMarket, stalePrice);
+            assert.fail("Expected InvalidOracleAccount");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidOracleAccount");
+        }
+
+        await resolveFrom(freshMarket, freshPrice);
+
+        const fresh = await program.account.market.fetch(freshMarket);
+        assert.equal(fresh.resolved, true);
+        assert.equal(fresh.winningOutcome, 0);
+        assert.equal((await program.account.market.fetch(staleMarket)).resolved, false);
+
+        console.log("✅ Pyth market resolved from a fresh price, stale price rejected");
+    });
+
+    it("Trades outcome tokens on a tokenized market", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const tokenMarketId = protocol.marketCount.toNumber();
+
+        [tokenMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(tokenMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will BTC flip ETH gas fees?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        outcomeMints = [0, 1].map((outcome) =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("outcome_mint"), tokenMarketPda.toBuffer(), Buffer.from([outcome])],
+                program.programId
+            )[0]
+        );
+
+        // Every mint is created in the same instruction, or none is
+        const mintAccounts = outcomeMints.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+        try {
+            await program.methods
+                .initOutcomeMints()
+                .accounts({
+                    market: tokenMarketPda,
+                    creator: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .remainingAccounts(mintAccounts.slice(0, 1))
+                .rpc();
+            assert.fail("Expected InvalidOutcomeAccounts");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidOutcomeAccounts");
+        }
+
+        await program.methods
+            .initOutcomeMints()
+            .accounts({
+                market: tokenMarketPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(mintAccounts)
+            .rpc();
+        for (const mint of outcomeMints) {
+            const info = await provider.connection.getParsedAccountInfo(mint);
+            assert.equal((info.value.data as any).parsed.info.mintAuthority, tokenMarketPda.toBase58());
+        }
+
+        // placeBet mints the shares as outcome tokens instead of opening a position
+        const outcomeAccounts = await Promise.all(
+            outcomeMints.map((mint) => getAssociatedTokenAddress(mint, provider.wallet.publicKey))
+        );
+        const bet = (outcome: number, amount: number, mint = outcomeMints[outcome]) =>
+            program.methods
+                .placeBet(outcome, new anchor.BN(amount), new anchor.BN(0))
+                .accounts({
+                    market: tokenMarketPda,
+                    position: null,
+                    outcomeMint: mint,
+                    userOutcomeAccount: outcomeAccounts[outcomeMints.indexOf(mint)],
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+        for (const [outcome, amount] of [[0, 10_000000], [1, 5_000000]]) {
+            await bet(outcome, amount);
+        }
+
+        const [yesAccount] = outcomeAccounts;
+        assert.equal((await provider.connection.getTokenAccountBalance(yesAccount)).value.amount, "14962488");
+        assert.equal((await program.account.market.fetch(tokenMarketPda)).positionCount.toNumber(), 0);
+
+        // The tokens must be those of the outcome bet on
+        try {
+            await bet(0, 1_000000, outcomeMints[1]);
+            assert.fail("Expected InvalidOutcomeAccounts");
+        } catch (e) {
+            assert.include(e.toString(), "InvalidOutcomeAccounts");
+        }
+
+        // Positions can no longer be opened on this market
+        try {
+            await program.methods
+                .placeBet(0, new anchor.BN(1_000000), new anchor.BN(0))
+                .accounts({
+                    market: tokenMarketPda,
+                    outcomeMint: outcomeMints[0],
+                    userOutcomeAccount: yesAccount,
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected placeBet to fail");
+        } catch (e) {
+            assert.include(e.toString(), "OutcomeTokenMarket");
+        }
+
+        // The authority fee recipient is the wallet, so its ATA is userTokenAccount
+        const devAta = await getAssociatedTokenAddress(usdcMint.publicKey, devRecipient, true);
+        try {
+            await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+                createAssociatedTokenAccountInstruction(provider.wallet.publicKey, devAta, devRecipient, usdcMint.publicKey)
+            ));
+        } catch (e) {
+            // Account may already exist
+        }
+
+        // withdrawFromPosition burns them and sells them to the AMM
+        await program.methods
+            .withdrawFromPosition(new anchor.BN(2_000000), new anchor.BN(0))
+            .accounts({
+                market: tokenMarketPda,
+                position: null,
+                outcomeMint: outcomeMints[0],
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                userOutcomeAccount: yesAccount,
+                authorityFeeRecipient: authorityFeeRecipient,
+                protocolTokenAccount: userTokenAccount,
+                devTokenAccount: devAta,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        assert.equal((await provider.connection.getTokenAccountBalance(yesAccount)).value.amount, "12962488");
+        const market = await program.account.market.fetch(tokenMarketPda);
+        assert.equal(market.outcomeMintCount, 2);
+        // The 2 sold shares went back into the pool, which paid out 0.950869 USDC for them
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [11_041643, 9_056627]);
+        assert.equal(market.outcomeShares[0].toNumber(), 12_962488);
+
+        console.log("✅ Outcome tokens minted by placeBet and burned by withdrawFromPosition");
+    });
+
+    it("Splits collateral into complete sets and merges any full set back", async () => {
+        const outcomeAccounts = await Promise.all(
+            outcomeMints.map((mint) => getAssociatedTokenAddress(mint, provider.wallet.publicKey))
+        );
+        const remainingAccounts = outcomeMints.flatMap((mint, i) => [
+            { pubkey: mint, isWritable: true, isSigner: false },
+            { pubkey: outcomeAccounts[i], isWritable: true, isSigner: false },
+        ]);
+        const balances = async () => Promise.all(
+            outcomeAccounts.map(async (a) => BigInt((await provider.connection.getTokenAccountBalance(a)).value.amount))
+        );
+
+        const before = await balances();
+        const marketBefore = await program.account.market.fetch(tokenMarketPda);
+        const poolsBefore = marketBefore.outcomePools.map((p) => p.toNumber());
+
+        await program.methods
+            .splitCollateral(new anchor.BN(3_000000))
+            .accounts({
+                market: tokenMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(remainingAccounts)
+            .rpc();
+
+        let after = await balances();
+        after.forEach((b, i) => assert.equal(b - before[i], BigInt(3_000000)));
+
+        await program.methods
+            .mergePositions(new anchor.BN(1_000000))
+            .accounts({
+                market: tokenMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(remainingAccounts)
+            .rpc();
+
+        after = await balances();
+        after.forEach((b, i) => assert.equal(b - before[i], BigInt(2_000000)));
+
+        let market = await program.account.market.fetch(tokenMarketPda);
+        // Split tokens are held by traders like bought ones, and never touch the AMM pools
+        assert.deepEqual(market.outcomeShares.map((s) => s.toNumber()), [14_962488, 16_947504]);
+        assert.equal(market.totalVolume.toNumber(), marketBefore.totalVolume.toNumber() + 2_000000);
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), poolsBefore);
+
+        // Tokens bought on the AMM merge at par too
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        await program.methods
+            .mergePositions(new anchor.BN(10_000000))
+            .accounts({
+                market: tokenMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(remainingAccounts)
+            .rpc();
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(10_000000));
+
+        market = await program.account.market.fetch(tokenMarketPda);
+        assert.deepEqual(market.outcomeShares.map((s) => s.toNumber()), [4_962488, 6_947504]);
+        assert.equal(market.totalVolume.toNumber(), marketBefore.totalVolume.toNumber() - 8_000000);
+
+        console.log("✅ Split 3 USDC into complete sets and merged 11 back at par");
+    });
+
+    it("Cancels and claims outcome tokens through the position instructions", async () => {
+        const { wallet: trader, tokenAccount: traderTokenAccount } = await fundedWallet(10_000000);
+        const balance = async (account: PublicKey) =>
+            BigInt((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+        const { marketCount } = await program.account.protocol.fetch(protocolPda);
+        const [market] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), marketCount.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const endTime = (await clockNow()) + 4;
+        await program.methods
+            .createMarket("Will the upgrade ship on time?", ["Yes", "No"], new anchor.BN(endTime), new anchor.BN(endTime + 86400 * 7), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const mints = [0, 1].map((outcome) =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("outcome_mint"), market.toBuffer(), Buffer.from([outcome])],
+                program.programId
+            )[0]
+        );
+        await program.methods
+            .initOutcomeMints()
+            .accounts({
+                market,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(mints.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+            .rpc();
+        const traderAccounts = await Promise.all(mints.map((mint) => getAssociatedTokenAddress(mint, trader.publicKey)));
+
+        for (const [outcome, amount] of [[0, 3_000000], [1, 2_000000]]) {
+            await program.methods
+                .placeBet(outcome, new anchor.BN(amount), new anchor.BN(0))
+                .accounts({
+                    market,
+                    position: null,
+                    outcomeMint: mints[outcome],
+                    userOutcomeAccount: traderAccounts[outcome],
+                    user: trader.publicKey,
+                    userTokenAccount: traderTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .signers([trader])
+                .rpc();
+        }
+
+        // The authority fee recipient is the wallet, so its ATA is userTokenAccount
+        const devAta = await getAssociatedTokenAddress(usdcMint.publicKey, devRecipient, true);
+        const feeAccounts = {
+            authorityFeeRecipient: authorityFeeRecipient,
+            protocolTokenAccount: userTokenAccount,
+            devTokenAccount: devAta,
+        };
+
+        // Cancelling burns every No token and sells them back before the end
+        const beforeCancel = await balance(traderTokenAccount);
+        await program.methods
+            .cancelPosition(new anchor.BN(0))
+            .accounts({
+                market,
+                position: null,
+                outcomeMint: mints[1],
+                userOutcomeAccount: traderAccounts[1],
+                user: trader.publicKey,
+                userTokenAccount: traderTokenAccount,
+                ...feeAccounts,
+                tokenMint: usdcMint.publicKey,
+            })
+            .signers([trader])
+            .rpc();
+        assert.equal(await balance(traderAccounts[1]), BigInt(0));
+        assert.isAbove(Number(await balance(traderTokenAccount) - beforeCancel), 0);
+
+        await waitUntil(endTime);
+        await program.methods
+            .resolveMarket(0)
+            .accounts({
+                market,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        // Past the 1 second dispute window
+        await waitUntil((await clockNow()) + 2);
+        await program.methods
+            .finalizeResolution()
+            .accounts({
+                market,
+                proposerTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // Claiming burns the winning Yes tokens and pays one unit each, less the protocol fee
+        const { protocolFeeBps } = await program.account.protocol.fetch(protocolPda);
+        const yesShares = await balance(traderAccounts[0]);
+        const beforeClaim = await balance(traderTokenAccount);
+        await program.methods
+            .claimWinnings()
+            .accounts({
+                market,
+                position: null,
+                outcomeMint: mints[0],
+                userOutcomeAccount: traderAccounts[0],
+                user: trader.publicKey,
+                userTokenAccount: traderTokenAccount,
+                ...feeAccounts,
+                tokenMint: usdcMint.publicKey,
+            })
+            .signers([trader])
+            .rpc();
+
+        assert.equal(await balance(traderAccounts[0]), BigInt(0));
+        assert.equal(
+            await balance(traderTokenAccount) - beforeClaim,
+            yesShares - yesShares * BigInt(protocolFeeBps) / BigInt(10000)
+        );
+
+        console.log("✅ Outcome tokens cancelled and claimed through cancelPosition and claimWinnings");
+    });
+
+    it("Transfers a position to another wallet", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const transferMarketId = protocol.marketCount.toNumber();
+
+        const [transferMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(transferMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the launch slip?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .placeBet(1, new anchor.BN(4_000000), new anchor.BN(0))
+            .accounts({
+                market: transferMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const newOwner = Keypair.generate().publicKey;
+        const positionSeeds = (owner: PublicKey) => PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                owner.toBuffer(),
+                transferMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        )[0];
+        const oldPositionPda = positionSeeds(provider.wallet.publicKey);
+        const newPositionPda = positionSeeds(newOwner);
+
+        await program.methods
+            .transferPosition()
+            .accounts({
+                market: transferMarketPda,
+                position: oldPositionPda,
+                user: provider.wallet.publicKey,
+                newOwner: newOwner,
+            })
+            .rpc();
+
+        const position = await program.account.position.fetch(newPositionPda);
+        assert.ok(position.user.equals(newOwner));
+        assert.equal(position.outcome, 1);
+        assert.equal(position.amount.toNumber(), 6_839015);
+        assert.equal(position.cost.toNumber(), 4_000000);
+        assert.equal(await provider.connection.getAccountInfo(oldPositionPda), null);
+
+        console.log("✅ Position moved to", newOwner.toBase58());
+    });
+
+    it("Mints a Core asset for a position", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const assetMarketId = protocol.marketCount.toNumber();
+
+        const [assetMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(assetMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the upgrade ship on time?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const [assetPositionPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                assetMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        );
+
+        const positionAsset = Keypair.generate();
+        await program.methods
+            .placeBet(0, new anchor.BN(2_000000), new anchor.BN(0))
+            .accounts({
+                market: assetMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                positionAsset: positionAsset.publicKey,
+                mplCoreProgram: MPL_CORE_PROGRAM_ID,
+                tokenMint: usdcMint.publicKey,
+            })
+            .signers([positionAsset])
+            .rpc();
+
+        const position = await program.account.position.fetch(assetPositionPda);
+        assert.ok(position.asset.equals(positionAsset.publicKey));
+
+        // AssetV1: key (1) | owner (32) | ...
+        const assetAccount = await provider.connection.getAccountInfo(positionAsset.publicKey);
+        assert.ok(assetAccount.owner.equals(MPL_CORE_PROGRAM_ID));
+        assert.ok(new PublicKey(assetAccount.data.subarray(1, 33)).equals(provider.wallet.publicKey));
+
+        // The asset, not the Position account, carries ownership
+        try {
+            await program.methods
+                .transferPosition()
+                .accounts({
+                    market: assetMarketPda,
+                    position: assetPositionPda,
+                    user: provider.wallet.publicKey,
+                    newOwner: Keypair.generate().publicKey,
+                })
+                .rpc();
+            assert.fail("Expected transferPosition to fail");
+        } catch (e) {
+            assert.include(e.toString(), "PositionHasAsset");
+        }
+
+        console.log("✅ Position mirrored by Core asset", positionAsset.publicKey.toBase58());
+    });
+
+    it("Adds and removes AMM liquidity", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const lpMarketId = protocol.marketCount.toNumber();
+
+        const [lpMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(lpMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const [lpMint] = PublicKey.findProgramAddressSync(
+            [Buffer.from("lp_mint"), lpMarketPda.toBuffer()],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the vote pass?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, null, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .addLiquidity(new anchor.BN(20_000000))
+            .accounts({
+                market: lpMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userLpAccount = await getAssociatedTokenAddress(lpMint, provider.wallet.publicKey);
+        assert.equal((await provider.connection.getTokenAccountBalance(userLpAccount)).value.amount, "20000000");
+
+        let market = await program.account.market.fetch(lpMarketPda);
+        // An empty market is funded evenly
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [20_000000, 20_000000]);
+        assert.deepEqual(market.lpReserve.map((p) => p.toNumber()), [0, 0]);
+        assert.equal(market.lpShares.toNumber(), 20_000000);
+
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        await program.methods
+            .removeLiquidity(new anchor.BN(5_000000), new anchor.BN(5_000000))
+            .accounts({
+                market: lpMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                userLpAccount: userLpAccount,
+                authorityFeeRecipient: authorityFeeRecipient,
+                protocolTokenAccount: null,
+                devTokenAccount: null,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // The pools were balanced, so the shares merge at par and nothing is sold or charged
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(5_000000));
+
+        market = await program.account.market.fetch(lpMarketPda);
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [15_000000, 15_000000]);
+        assert.equal(market.lpShares.toNumber(), 15_000000);
+
+        console.log("✅ Liquidity added and 5 USDC removed from balanced pools");
+    });
+
+    it("Prices bets on the AMM curve", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const curveMarketId = protocol.marketCount.toNumber();
+
+        const [curveMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(curveMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the bridge open on time?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // 4.985 USDC after the 0.3% fee against pools of 10/10: the No pool grows to 14.985 and the
+        // Yes pool keeps 10 * 10 / 14.985 rounded up, so the bet buys 14.985 - 6.673341 shares
+        try {
+            await program.methods
+                .placeBet(0, new anchor.BN(5_000000), new anchor.BN(8_311660))
+                .accounts({
+                    market: curveMarketPda,
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected SlippageExceeded");
+        } catch (e) {
+            assert.include(e.toString(), "SlippageExceeded");
+        }
+
+        const [curvePositionPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                curveMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        );
+
+        await program.methods
+            .placeBet(0, new anchor.BN(5_000000), new anchor.BN(8_311659))
+            .accounts({
+                market: curveMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const position = await program.account.position.fetch(curvePositionPda);
+        assert.equal(position.amount.toNumber(), 8_311659);
+        assert.equal(position.cost.toNumber(), 5_000000);
+
+        const market = await program.account.market.fetch(curveMarketPda);
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [6_673341, 14_985000]);
+        assert.deepEqual(market.outcomeShares.map((p) => p.toNumber()), [8_311659, 0]);
+        assert.equal(market.totalVolume.toNumber(), 14_985000);
+        assert.equal(market.lpFees.toNumber(), 15000);
+
+        // Yes now costs 14.985 / (6.673341 + 14.985) ≈ 0.69
+        const [yesPool, noPool] = market.outcomePools.map((p) => p.toNumber());
+        assert.approximately(noPool / (yesPool + noPool), 0.6919, 0.0001);
+
+        console.log("✅ 5 USDC bought 8.31 shares on the curve");
+    });
+
+    it("Seeds a market at chosen odds and returns the seed", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const seedMarketId = protocol.marketCount.toNumber();
+
+        const [seedMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(seedMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        const seed = { amount: new anchor.BN(10_000000), oddsBps: [6000, 4000] };
+
+        await program.methods
+            .createMarket("Will the merger close?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, seed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // The favourite's pool is scaled down to 10 * 0.4 / 0.6 and the rest of its shares are held
+        // in reserve, so Yes opens at 10 / (6.666666 + 10) = 0.6
+        const market = await program.account.market.fetch(seedMarketPda);
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [6_666666, 10_000000]);
+        assert.deepEqual(market.lpReserve.map((p) => p.toNumber()), [3_333334, 0]);
+        assert.equal(market.seedShares.toNumber(), 10_000000);
+        const [yesPool, noPool] = market.outcomePools.map((p) => p.toNumber());
+        assert.approximately(noPool / (yesPool + noPool), 0.6, 0.000001);
+
+        await program.methods
+            .voidMarket()
+            .accounts({
+                market: seedMarketPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        await program.methods
+            .claimSeedLiquidity()
+            .accounts({
+                market: seedMarketPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                authorityFeeRecipient: authorityFeeRecipient,
+                protocolTokenAccount: null,
+                devTokenAccount: null,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(10_000000));
+
+        console.log("✅ Market opened at 60/40 and the seed was returned after voiding");
+    });
+
+    it("Prices an LMSR market and returns its subsidy", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const lmsrMarketId = protocol.marketCount.toNumber();
+
+        const [lmsrMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(lmsrMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the festival sell out?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, null, { lmsr: { b: new anchor.BN(10_000000) } })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // b * ln(2), rounded up
+        let market = await program.account.market.fetch(lmsrMarketPda);
+        assert.equal(market.subsidy.toNumber(), 6_931472);
+
+        await program.methods
+            .placeBet(0, new anchor.BN(5_000000), new anchor.BN(8_290000))
+            .accounts({
+                market: lmsrMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // 4.985 USDC after the 0.3% fee buys b * ln(2 * e^0.4985 - 1) ≈ 8.296 shares, the pools are not used
+        market = await program.account.market.fetch(lmsrMarketPda);
+        assert.approximately(market.outcomeShares[0].toNumber(), 8_296430, 5);
+        assert.deepEqual(market.outcomePools.map((p) => p.toNumber()), [0, 0]);
+
+        await program.methods
+            .voidMarket()
+            .accounts({
+                market: lmsrMarketPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        // The bettor takes back the 5 USDC it paid, fee included, not a share of the escrow
+        const [lmsrPositionPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                lmsrMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        );
+        const refundBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        await program.methods
+            .claimRefund()
+            .accounts({
+                market: lmsrMarketPda,
+                position: lmsrPositionPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceBefore - refundBefore, BigInt(5_000000));
+
+        await program.methods
+            .claimSeedLiquidity()
+            .accounts({
+                market: lmsrMarketPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                authorityFeeRecipient: authorityFeeRecipient,
+                protocolTokenAccount: null,
+                devTokenAccount: null,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(6_931472));
+
+        console.log("✅ LMSR bet priced and the subsidy returned after voiding");
+    });
+
+    it("Crosses resting limit orders and cancels the remainder", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const orderMarketId = protocol.marketCount.toNumber();
+
+        const [orderMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(orderMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the bridge open on time?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .placeBet(0, new anchor.BN(10_000000), new anchor.BN(0))
+            .accounts({
+                market: orderMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const positionAt = (id: number) => PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                orderMarketPda.toBuffer(),
+                new anchor.BN(id).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        )[0];
+        const orderAt = (id: number) => {
+            const [order] = PublicKey.findProgramAddressSync(
+                [Buffer.from("order"), orderMarketPda.toBuffer(), new anchor.BN(id).toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            const [vault] = PublicKey.findProgramAddressSync(
+                [Buffer.from("order_vault"), order.toBuffer()],
+                program.programId
+            );
+            return { order, vault };
+        };
+        const sellerPosition = positionAt(0);
+        const buyerPosition = positionAt(1);
+        const sell = orderAt(0);
+        const buy = orderAt(1);
+        const expiry = new anchor.BN(now + 3600);
+
+        // Ask 4 shares at 0.60 and bid 3 USDC at up to 0.70
+        await program.methods
+            .placeSellOrder(new anchor.BN(4_000000), 6000, expiry)
+            .accounts({
+                market: orderMarketPda,
+                position: sellerPosition,
+                user: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        let position = await program.account.position.fetch(sellerPosition);
+        assert.equal(position.locked.toNumber(), 4_000000);
+
+        await program.methods
+            .placeBuyOrder(0, new anchor.BN(3_000000), 7000, expiry)
+            .accounts({
+                market: orderMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // The ask was resting first, so all 4 shares cross at 0.60 for 2.4 USDC
+        await program.methods
+            .fillOrder()
+            .accounts({
+                market: orderMarketPda,
+                order: buy.order,
+                position: buyerPosition,
+                counterOrder: sell.order,
+                counterVault: sell.vault,
+                counterPosition: sellerPosition,
+                authorityFeeRecipient: authorityFeeRecipient,
+                protocolTokenAccount: null,
+                devTokenAccount: null,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // 10 USDC bought 14.962488 shares on the seeded pools, and 4 of them leave with their share of its cost
+        position = await program.account.position.fetch(sellerPosition);
+        assert.equal(position.amount.toNumber(), 10_962488);
+        assert.equal(position.cost.toNumber(), 7_326647);
+        assert.equal(position.locked.toNumber(), 0);
+        const buyer = await program.account.position.fetch(buyerPosition);
+        assert.equal(buyer.amount.toNumber(), 4_000000);
+        assert.equal(buyer.cost.toNumber(), 2_400000);
+        assert.equal((await program.account.order.fetch(buy.order)).remaining.toNumber(), 600000);
+
+        // The buy order locks no shares, but it still points at its position
+        assert.equal(buyer.openOrders, 1);
+        try {
+            await program.methods
+                .transferPosition()
+                .accounts({
+                    market: orderMarketPda,
+                    position: buyerPosition,
+                    user: provider.wallet.publicKey,
+                    newOwner: Keypair.generate().publicKey,
+                })
+                .rpc();
+            assert.fail("Expected transferPosition to fail");
+        } catch (e) {
+            assert.include(e.toString(), "PositionHasOpenOrders");
+        }
+
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        for (const [{ order, vault }, orderPosition] of [[sell, sellerPosition], [buy, buyerPosition]] as const) {
+            await program.methods
+                .cancelOrder()
+                .accounts({
+                    market: orderMarketPda,
+                    order: order,
+                    orderVault: vault,
+                    position: orderPosition,
+                    owner: provider.wallet.publicKey,
+                    ownerTokenAccount: userTokenAccount,
+                    caller: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+        }
+
+        // 2.4 USDC of sale proceeds and the 0.6 USDC left on the bid
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(3_000000));
+        assert.isNull(await program.account.order.fetchNullable(buy.order));
+        assert.equal((await program.account.position.fetch(buyerPosition)).openOrders, 0);
+
+        console.log("✅ Limit orders crossed at the resting price and cancelled");
+    });
+
+    it("Matches orders on a market's order book and settles them into positions", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const bookMarketId = protocol.marketCount.toNumber();
+
+        const [bookMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(bookMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const [orderBookPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("order_book"), bookMarketPda.toBuffer()],
+            program.programId
+        );
+        const [bookVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("book_vault"), bookMarketPda.toBuffer()],
+            program.programId
+        );
+        const positionAt = (id: number) => PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                bookMarketPda.toBuffer(),
+                new anchor.BN(id).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        )[0];
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the launch slip?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, null, { orderBook: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .initOrderBook()
+            .accounts({
+                market: bookMarketPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // AMM bets are not available on a book market
+        try {
+            await program.methods
+                .placeBet(0, new anchor.BN(1_000000), new anchor.BN(0))
+                .accounts({
+                    market: bookMarketPda,
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            assert.fail("Should have thrown error");
+        } catch (e) {
+            assert.include(e.toString(), "UnsupportedPricingMode");
+        }
+
+        const userBalanceStart = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        // Bid 5 YES at 0.60 rests, then an ask for 3 at 0.55 takes 3 of them at 0.60
+        await program.methods
+            .placeBookOrder({ bid: {} }, 6000, new anchor.BN(5_000000))
+            .accounts({
+                market: bookMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        await program.methods
+            .placeBookOrder({ ask: {} }, 5500, new anchor.BN(3_000000))
+            .accounts({
+                market: bookMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // 3 USDC for the bid and 3 × 0.40 for the ask
+        const userBalanceAfterOrders = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceStart - userBalanceAfterOrders, BigInt(4_200000));
+
+        let orderBook = await program.account.orderBook.fetch(orderBookPda);
+        assert.equal(orderBook.bids.length, 1);
+        assert.equal(orderBook.bids[0].quantity.toNumber(), 2_000000);
+        assert.equal(orderBook.asks.length, 0);
+        assert.equal(orderBook.events.length, 1);
+
+        const bidPosition = positionAt(0);
+        const askPosition = positionAt(1);
+        await program.methods
+            .consumeBookEvents(8)
+            .accounts({
+                market: bookMarketPda,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts([
+                { pubkey: bidPosition, isWritable: true, isSigner: false },
+                { pubkey: askPosition, isWritable: true, isSigner: false },
+            ])
+            .rpc();
+
+        assert.equal((await program.account.position.fetch(bidPosition)).amount.toNumber(), 3_000000);
+        const ask = await program.account.position.fetch(askPosition);
+        assert.equal(ask.outcome, 1);
+        assert.equal(ask.amount.toNumber(), 3_000000);
+
+        const market = await program.account.market.fetch(bookMarketPda);
+        assert.deepEqual(market.outcomeShares.map((s) => s.toNumber()), [3_000000, 3_000000]);
+        assert.deepEqual(market.outcomeCosts.map((c) => c.toNumber()), [1_800000, 1_200000]);
+        assert.equal(market.totalVolume.toNumber(), 3_000000);
+        assert.equal(market.pendingFills.toNumber(), 0);
+
+        await program.methods
+            .cancelBookOrder(new anchor.BN(0))
+            .accounts({
+                market: bookMarketPda,
+                owner: provider.wallet.publicKey,
+                ownerTokenAccount: userTokenAccount,
+                caller: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // The unfilled 2 shares of the bid at 0.60
+        const userBalanceEnd = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceEnd - userBalanceAfterOrders, BigInt(1_200000));
+        assert.equal((await provider.connection.getTokenAccountBalance(bookVault)).value.amount, "0");
+
+        orderBook = await program.account.orderBook.fetch(orderBookPda);
+        assert.equal(orderBook.bids.length, 0);
+
+        console.log("✅ Book orders matched, settled by the crank and cancelled");
+    });
+
+    it("Settles peer-to-peer offers and refunds them on void", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const offerMarketId = protocol.marketCount.toNumber();
+
+        const [offerMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(offerMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const offerAt = (id: number) => {
+            const [offer] = PublicKey.findProgramAddressSync(
+                [Buffer.from("offer"), offerMarketPda.toBuffer(), new anchor.BN(id).toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            const [vault] = PublicKey.findProgramAddressSync(
+                [Buffer.from("offer_vault"), offer.toBuffer()],
+                program.programId
+            );
+            return { offer, vault };
+        };
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will it snow on New Year's Day?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, null, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // The taker gets 50 USDC of their own
+        const taker = Keypair.generate();
+        const takerTokenAccount = await getAssociatedTokenAddress(usdcMint.publicKey, taker.publicKey);
+        await provider.sendAndConfirm(new anchor.web3.Transaction().add(
+            createAssociatedTokenAccountInstruction(provider.wallet.publicKey, takerTokenAccount, taker.publicKey, usdcMint.publicKey),
+            createMintToInstruction(usdcMint.publicKey, takerTokenAccount, provider.wallet.publicKey, 50_000000)
+        ));
+
+        // 10 USDC on Yes at 3:1 for the taker only, and an open offer nobody takes
+        const expiry = new anchor.BN(now + 3600);
+        await program.methods
+            .makeOffer(0, new anchor.BN(10_000000), 3, 1, taker.publicKey, expiry)
+            .accounts({
+                market: offerMarketPda,
+                maker: provider.wallet.publicKey,
+                makerTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        await program.methods
+            .makeOffer(1, new anchor.BN(5_000000), 1, 1, null, expiry)
+            .accounts({
+                market: offerMarketPda,
+                maker: provider.wallet.publicKey,
+                makerTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const challenge = offerAt(0);
+        const open = offerAt(1);
+        assert.equal((await program.account.offer.fetch(challenge.offer)).takerStake.toNumber(), 30_000000);
+
+        await program.methods
+            .acceptOffer()
+            .accounts({
+                market: offerMarketPda,
+                offer: challenge.offer,
+                taker: taker.publicKey,
+                takerTokenAccount: takerTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .signers([taker])
+            .rpc();
+
+        assert.equal((await provider.connection.getTokenAccountBalance(challenge.vault)).value.amount, "40000000");
+        assert.equal((await provider.connection.getTokenAccountBalance(takerTokenAccount)).value.amount, "20000000");
+
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        await program.methods
+            .cancelOffer()
+            .accounts({
+                market: offerMarketPda,
+                offer: open.offer,
+                maker: provider.wallet.publicKey,
+                makerTokenAccount: userTokenAccount,
+                caller: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .voidMarket()
+            .accounts({
+                market: offerMarketPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        // Voided: both stakes go back, and the pools were never touched
+        await program.methods
+            .settleOffer()
+            .accounts({
+                market: offerMarketPda,
+                offer: challenge.offer,
+                maker: provider.wallet.publicKey,
+                makerTokenAccount: userTokenAccount,
+                takerTokenAccount: takerTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter - userBalanceBefore, BigInt(15_000000));
+        assert.equal((await provider.connection.getTokenAccountBalance(takerTokenAccount)).value.amount, "50000000");
+        assert.isNull(await program.account.offer.fetchNullable(challenge.offer));
+
+        const market = await program.account.market.fetch(offerMarketPda);
+        assert.equal(market.totalVolume.toNumber(), 0);
+
+        console.log("✅ Offers accepted, cancelled and refunded on void");
+    });
+
+    it("Underwrites a parlay and refunds it when its legs are voided", async () => {
+        const [parlayVaultPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("parlay_vault"), usdcMint.publicKey.toBuffer()],
+            program.programId
+        );
+        const [reserve] = PublicKey.findProgramAddressSync(
+            [Buffer.from("parlay_reserve"), parlayVaultPda.toBuffer()],
+            program.programId
+        );
+
+        await program.methods
+            .initParlayVault()
+            .accounts({
+                authority: provider.wallet.publicKey,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        await program.methods
+            .fundParlayVault(new anchor.BN(100_000000))
+            .accounts({
+                funder: provider.wallet.publicKey,
+                funderTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        // Two legs seeded at even odds and at 25% for Yes
+        const legMarkets: PublicKey[] = [];
+        for (const oddsBps of [[5000, 5000], [2500, 7500]]) {
+            const protocol = await program.account.protocol.fetch(protocolPda);
+            const [legMarketPda] = PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), protocol.marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .createMarket("Parlay leg", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, { amount: new anchor.BN(10_000000), oddsBps }, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    creatorTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            legMarkets.push(legMarketPda);
+        }
+        const legAccounts = legMarkets.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+
+        const [parlayPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("parlay"), parlayVaultPda.toBuffer(), new anchor.BN(0).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const userBalanceBefore = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+
+        await program.methods
+            .createParlay(new anchor.BN(2_000000), Buffer.from([0, 0]), new anchor.BN(0))
+            .accounts({
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(legAccounts)
+            .rpc();
+
+        // Odds of 1 / price on each leg's seeded pools, about 2 and 4, each less the AMM fee (neither leg
+        // has traded, so there is no price snapshot yet)
+        const { ammFee } = await program.account.protocol.fetch(protocolPda);
+        const precision = BigInt(1_000_000_000);
+        const poolOdds = (pools: bigint[]) => {
+            const inverseSum = pools.reduce((sum, pool) => sum + pools[0] * precision / pool, BigInt(0));
+            return precision * precision / (precision * precision / inverseSum);
+        };
+        const legPools = await Promise.all(legMarkets.map(async (leg) =>
+            (await program.account.market.fetch(leg)).outcomePools.map((p) => BigInt(p.toString()))
+        ));
+        const legOdds = legPools.map((pools) => poolOdds(pools) * BigInt(10000 - ammFee) / BigInt(10000));
+        const maxPayout = legOdds.reduce((payout, odds) => payout * odds / precision, BigInt(2_000000));
+
+        const parlay = await program.account.parlay.fetch(parlayPda);
+        assert.deepEqual(parlay.legs.map((leg) => BigInt(leg.odds.toString())), legOdds);
+        assert.equal(parlay.maxPayout.toString(), maxPayout.toString());
+        assert.equal((await program.account.parlayVault.fetch(parlayVaultPda)).liabilities.toString(), maxPayout.toString());
+
+        // A market with an open leg cannot become conditional
+        for (const leg of legMarkets) {
+            assert.equal((await program.account.market.fetch(leg)).parlayLegs.toNumber(), 1);
+        }
+        try {
+            await program.methods
+                .linkParentMarket(0)
+                .accounts({
+                    market: legMarkets[1],
+                    parentMarket: legMarkets[0],
+                    creator: provider.wallet.publicKey,
+                })
+                .rpc();
+            assert.fail("Expected MarketHasBets");
+        } catch (e) {
+            assert.include(e.toString(), "MarketHasBets");
+        }
+
+        // Buying No snapshots the even odds first, so Yes cannot be parlayed at the moved price
+        const betAmount = BigInt(5_000000);
+        await program.methods
+            .placeBet(1, new anchor.BN(betAmount.toString()), new anchor.BN(0))
+            .accounts({
+                market: legMarkets[0],
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        try {
+            await program.methods
+                .createParlay(new anchor.BN(2_000000), Buffer.from([0, 0]), new anchor.BN(0))
+                .accounts({
+                    user: provider.wallet.publicKey,
+                    userTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .remainingAccounts(legAccounts)
+                .rpc();
+            assert.fail("Expected LegPriceMoved");
+        } catch (e) {
+            assert.include(e.toString(), "LegPriceMoved");
+        }
+
+        for (const legMarketPda of legMarkets) {
+            await program.methods
+                .voidMarket()
+                .accounts({
+                    market: legMarketPda,
+                    creator: provider.wallet.publicKey,
+                })
+                .rpc();
+        }
+
+        // Every leg dropped: the stake comes back
+        await program.methods
+            .settleParlay()
+            .accounts({
+                parlay: parlayPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .remainingAccounts(legAccounts)
+            .rpc();
+
+        const userBalanceAfter = BigInt((await provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount);
+        assert.equal(userBalanceAfter, userBalanceBefore - betAmount);
+        assert.equal((await program.account.parlayVault.fetch(parlayVaultPda)).liabilities.toNumber(), 0);
+        assert.equal((await provider.connection.getTokenAccountBalance(reserve)).value.amount, "100000000");
+        assert.isNull(await program.account.parlay.fetchNullable(parlayPda));
+        for (const leg of legMarkets) {
+            assert.equal((await program.account.market.fetch(leg)).parlayLegs.toNumber(), 0);
+        }
+
+        console.log("✅ Parlay priced from the pools, rejected once they moved, reserved and refunded once voided");
+    });
+
+    it("Refunds a conditional market when its parent resolves the other way", async () => {
+        const marketAt = async () => {
+            const protocol = await program.account.protocol.fetch(protocolPda);
+            return PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), protocol.marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            )[0];
+        };
+
+        const now = Math.floor(Date.now() / 1000);
+        const markets: PublicKey[] = [];
+        for (const question of ["Will candidate A win?", "If A wins, will policy X pass?"]) {
+            const marketPda = await marketAt();
+            await program.methods
+                .createMarket(question, ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    creatorTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            markets.push(marketPda);
+        }
+        const [parentPda, childPda] = markets;
+
+        await program.methods
+            .linkParentMarket(0)
+            .accounts({
+                market: childPda,
+                parentMarket: parentPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        let child = await program.account.market.fetch(childPda);
+        assert.ok(child.parent.market.equals(parentPda));
+        assert.equal(child.parent.outcome, 0);
+
+        // A second bettor with 10 USDC
+        const { wallet: bettor, tokenAccount: bettorTokenAccount } = await fundedWallet(10_000000);
+
+        // Both buy Yes, the second one after the first has moved its price up
+        const bets = [
+            { user: provider.wallet.publicKey, tokenAccount: userTokenAccount, signers: [], amount: BigInt(4_000000) },
+            { user: bettor.publicKey, tokenAccount: bettorTokenAccount, signers: [bettor], amount: BigInt(2_000000) },
+        ];
+        for (const bet of bets) {
+            await program.methods
+                .placeBet(0, new anchor.BN(bet.amount.toString()), new anchor.BN(0))
+                .accounts({
+                    market: childPda,
+                    user: bet.user,
+                    userTokenAccount: bet.tokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .signers(bet.signers)
+                .rpc();
+        }
+        const positions = bets.map((bet, id) => PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                bet.user.toBuffer(),
+                childPda.toBuffer(),
+                new anchor.BN(id).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        )[0]);
+        const [early, late] = await Promise.all(positions.map((position) => program.account.position.fetch(position)));
+        assert.isAbove(
+            early.amount.toNumber() / early.cost.toNumber(),
+            late.amount.toNumber() / late.cost.toNumber()
+        );
+
+        // Still live while the parent is open
+        try {
+            await program.methods
+                .voidConditionalMarket()
+                .accounts({
+                    market: childPda,
+                    parentMarket: parentPda,
+                })
+                .rpc();
+            assert.fail("Should have thrown error");
+        } catch (e) {
+            assert.include(e.toString(), "MarketNotResolved");
+        }
+
+        await program.methods
+            .voidMarket()
+            .accounts({
+                market: parentPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+        await program.methods
+            .voidConditionalMarket()
+            .accounts({
+                market: childPda,
+                parentMarket: parentPda,
+            })
+            .rpc();
+
+        child = await program.account.market.fetch(childPda);
+        assert.equal(child.voided, true);
+
+        // Each bettor takes back exactly what they paid, whatever price they bought at
+        for (const [i, bet] of bets.entries()) {
+            const balanceBefore = BigInt((await provider.connection.getTokenAccountBalance(bet.tokenAccount)).value.amount);
+            await program.methods
+                .claimRefund()
+                .accounts({
+                    market: childPda,
+                    position: positions[i],
+                    user: bet.user,
+                    userTokenAccount: bet.tokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .signers(bet.signers)
+                .rpc();
+            const balanceAfter = BigInt((await provider.connection.getTokenAccountBalance(bet.tokenAccount)).value.amount);
+            assert.equal(balanceAfter - balanceBefore, bet.amount);
+        }
+
+        // Only the seed's collateral is left in escrow
+        const childEscrow = await getAssociatedTokenAddress(usdcMint.publicKey, childPda, true);
+        assert.equal((await provider.connection.getTokenAccountBalance(childEscrow)).value.amount, "10000000");
+
+        console.log("✅ Conditional market voided with its parent and each stake refunded at cost");
+    });
+
+    it("Settles disputes by arbitration and slashes the losing bond", async () => {
+        const bond = BigInt(1_000000);
+        await program.methods
+            .updateResolutionConfig(new anchor.BN(30), new anchor.BN(bond.toString()), null)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const { wallet: disputer, tokenAccount: disputerTokenAccount } = await fundedWallet(10_000000);
+        const balance = async (account: PublicKey) =>
+            BigInt((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+        // Each market is proposed as Yes, disputed, then ruled Yes, No or void
+        const endTime = (await clockNow()) + 5;
+        const rulings = [
+            { outcome: 0, proposerWon: true },
+            { outcome: 1, proposerWon: false },
+            { outcome: null, proposerWon: false },
+        ];
+        const markets: PublicKey[] = [];
+        while (markets.length < rulings.length) {
+            const { marketCount } = await program.account.protocol.fetch(protocolPda);
+            const [market] = PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            await program.methods
+                .createMarket("Will the bill pass?", ["Yes", "No"], new anchor.BN(endTime), new anchor.BN(endTime + 86400 * 7), { categorical: {} }, { creator: {} }, null, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            markets.push(market);
+        }
+
+        await waitUntil(endTime);
+        for (const [i, ruling] of rulings.entries()) {
+            const market = markets[i];
+            const [bondVault] = PublicKey.findProgramAddressSync(
+                [Buffer.from("bond_vault"), market.toBuffer()],
+                program.programId
+            );
+
+            await program.methods
+                .resolveMarket(0)
+                .accounts({
+                    market,
+                    creator: provider.wallet.publicKey,
+                    creatorTokenAccount: userTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            await program.methods
+                .disputeResolution()
+                .accounts({
+                    market,
+                    disputer: disputer.publicKey,
+                    disputerTokenAccount,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .signers([disputer])
+                .rpc();
+            assert.equal(await balance(bondVault), bond * BigInt(2));
+            assert.ok((await program.account.market.fetch(market)).proposal.disputer.equals(disputer.publicKey));
+
+            const arbitrate = (arbiter: Keypair | null, winnerTokenAccount: PublicKey) => {
+                const call = program.methods
+                    .arbitrateDispute(ruling.outcome, null)
+                    .accounts({
+                        market,
+                        arbiter: arbiter ? arbiter.publicKey : provider.wallet.publicKey,
+                        winnerTokenAccount,
+                        tokenMint: usdcMint.publicKey,
+                    });
+                return arbiter ? call.signers([arbiter]).rpc() : call.rpc();
+            };
+            const [winnerTokenAccount, loserTokenAccount] = ruling.proposerWon
+                ? [userTokenAccount, disputerTokenAccount]
+                : [disputerTokenAccount, userTokenAccount];
+
+            // Only the protocol authority or arbiter rules, and only the winner is paid
+            for (const [arbiter, account] of [[disputer, winnerTokenAccount], [null, loserTokenAccount]] as [Keypair | null, PublicKey][]) {
+                try {
+                    await arbitrate(arbiter, account);
+                    assert.fail("Expected Unauthorized");
+                } catch (e) {
+                    assert.include(e.toString(), "Unauthorized");
+                }
+            }
+
+            const winnerBefore = await balance(winnerTokenAccount);
+            await arbitrate(null, winnerTokenAccount);
+            assert.equal(await balance(winnerTokenAccount) - winnerBefore, bond * BigInt(2));
+            assert.equal(await balance(bondVault), BigInt(0));
+
+            const settled = await program.account.market.fetch(market);
+            assert.equal(settled.resolved, true);
+            if (ruling.outcome === null) {
+                assert.equal(settled.voided, true);
+                assert.isNull(settled.winningOutcome);
+            } else {
+                assert.equal(settled.voided, false);
+                assert.equal(settled.winningOutcome, ruling.outcome);
+            }
+        }
+
+        await program.methods
+            .updateResolutionConfig(new anchor.BN(1), new anchor.BN(0), null)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        console.log("✅ Disputes ruled for the proposer, the disputer and void, bonds paid to the winner");
+    });
+
+    it("Voids expired markets and returns the bonds of an unarbitrated dispute", async () => {
+        const bond = BigInt(1_000000);
+        const disputeWindow = 3;
+        await program.methods
+            .updateResolutionConfig(new anchor.BN(disputeWindow), new anchor.BN(bond.toString()), null)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const { wallet: disputer, tokenAccount: disputerTokenAccount } = await fundedWallet(10_000000);
+        const balance = async (account: PublicKey) =>
+            BigInt((await provider.connection.getTokenAccountBalance(account)).value.amount);
+
+        // Both markets end in a few seconds and must be resolved within the next few
+        const endTime = (await clockNow()) + 4;
+        const deadline = endTime + 4;
+        const markets: PublicKey[] = [];
+        while (markets.length < 2) {
+            const { marketCount } = await program.account.protocol.fetch(protocolPda);
+            const [market] = PublicKey.findProgramAddressSync(
+                [Buffer.from("market"), marketCount.toArrayLike(Buffer, "le", 8)],
+                program.programId
+            );
+            await program.methods
+                .createMarket("Will the launch slip?", ["Yes", "No"], new anchor.BN(endTime), new anchor.BN(deadline), { categorical: {} }, { creator: {} }, null, { constantProduct: {} })
+                .accounts({
+                    protocol: protocolPda,
+                    creator: provider.wallet.publicKey,
+                    tokenMint: usdcMint.publicKey,
+                })
+                .rpc();
+            markets.push(market);
+        }
+        const [unresolved, disputed] = markets;
+        const [bondVault] = PublicKey.findProgramAddressSync(
+            [Buffer.from("bond_vault"), disputed.toBuffer()],
+            program.programId
+        );
+
+        const voidExpired = (market: PublicKey, withBonds: boolean) =>
+            program.methods
+                .voidExpiredMarket()
+                .accounts({
+                    market,
+                    bondVault: withBonds ? bondVault : null,
+                    proposerTokenAccount: withBonds ? userTokenAccount : null,
+                    disputerTokenAccount: withBonds ? disputerTokenAccount : null,
+                })
+                .rpc();
+        const expectFailure = async (call: Promise<string>, error: string) => {
+            try {
+                await call;
+                assert.fail(`Expected ${error}`);
+            } catch (e) {
+                assert.include(e.toString(), error);
+            }
+        };
+
+        await expectFailure(voidExpired(unresolved, false), "ResolutionDeadlineNotReached");
+
+        // The second market is proposed and disputed, and nobody arbitrates
+        await waitUntil(endTime);
+        await program.methods
+            .resolveMarket(0)
+            .accounts({
+                market: disputed,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+        await program.methods
+            .disputeResolution()
+            .accounts({
+                market: disputed,
+                disputer: disputer.publicKey,
+                disputerTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .signers([disputer])
+            .rpc();
+
+        // Past the deadline the unresolved market is void, the disputed one gets another window
+        await waitUntil(deadline);
+        await voidExpired(unresolved, false);
+        const voided = await program.account.market.fetch(unresolved);
+        assert.equal(voided.resolved, true);
+        assert.equal(voided.voided, true);
+        await expectFailure(voidExpired(disputed, true), "ResolutionDeadlineNotReached");
+
+        const { proposal } = await program.account.market.fetch(disputed);
+        await waitUntil(Math.max(deadline, proposal.disputeDeadline.toNumber()) + disputeWindow);
+        await expectFailure(voidExpired(disputed, false), "MissingBondAccounts");
+
+        const proposerBefore = await balance(userTokenAccount);
+        const disputerBefore = await balance(disputerTokenAccount);
+        await voidExpired(disputed, true);
+
+        assert.equal(await balance(userTokenAccount) - proposerBefore, bond);
+        assert.equal(await balance(disputerTokenAccount) - disputerBefore, bond);
+        assert.equal(await balance(bondVault), BigInt(0));
+        assert.equal((await program.account.market.fetch(disputed)).voided, true);
+
+        await program.methods
+            .updateResolutionConfig(new anchor.BN(1), new anchor.BN(0), null)
+            .accounts({
+                authority: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        console.log("✅ Expired markets voided, disputed bonds returned to both sides");
+    });
+
+    it("Voids a market and refunds stakes", async () => {
+        const protocol = await program.account.protocol.fetch(protocolPda);
+        const voidMarketId = protocol.marketCount.toNumber();
+
+        const [voidMarketPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("market"), new anchor.BN(voidMarketId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+
+        const now = Math.floor(Date.now() / 1000);
+        await program.methods
+            .createMarket("Will the match be played?", ["Yes", "No"], new anchor.BN(now + 86400), new anchor.BN(now + 86400 * 8), { categorical: {} }, { creator: {} }, evenSeed, { constantProduct: {} })
+            .accounts({
+                protocol: protocolPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const [voidPositionPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("position"),
+                provider.wallet.publicKey.toBuffer(),
+                voidMarketPda.toBuffer(),
+                new anchor.BN(0).toArrayLike(Buffer, "le", 8)
+            ],
+            program.programId
+        );
+
+        const betAmount = new anchor.BN(7_000000);
+        await program.methods
+            .placeBet(0, betAmount, new anchor.BN(0))
+            .accounts({
+                market: voidMarketPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        await program.methods
+            .voidMarket()
+            .accounts({
+                market: voidMarketPda,
+                creator: provider.wallet.publicKey,
+            })
+            .rpc();
+
+        const market = await program.account.market.fetch(voidMarketPda);
+        assert.equal(market.voided, true);
+        assert.equal(market.resolved, true);
+
+        const userBalanceBefore = (
+            await provider.connection.getTokenAccountBalance(userTokenAccount)
+        ).value.amount;
+
+        await program.methods
+            .claimRefund()
+            .accounts({
+                market: voidMarketPda,
+                position: voidPositionPda,
+                user: provider.wallet.publicKey,
+                userTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        const userBalanceAfter = (
+            await provider.connection.getTokenAccountBalance(userTokenAccount)
+        ).value.amount;
+
+        assert.equal(
+            BigInt(userBalanceAfter) - BigInt(userBalanceBefore),
+            BigInt(betAmount.toNumber())
+        );
+
+        // Exactly the recorded cost left escrow, and only the seed is still there
+        const position = await program.account.position.fetch(voidPositionPda);
+        assert.equal(position.claimed, true);
+        assert.equal(position.cost.toNumber(), 0);
+        const marketAfter = await program.account.market.fetch(voidMarketPda);
+        assert.deepEqual(marketAfter.outcomeCosts.map((c) => c.toNumber()), [0, 0]);
+        const voidEscrow = await getAssociatedTokenAddress(usdcMint.publicKey, voidMarketPda, true);
+        assert.equal((await provider.connection.getTokenAccountBalance(voidEscrow)).value.amount, "10000000");
+
+        console.log("✅ Market voided, 7 USDC refunded without fee");
+    });
+
+    it("Resolves the market", async () => {
+        // Wait for end time or skip time check in test
+        const winningOutcome = 0;
+
+        // For testing, you may need to modify end_time or wait
+        // Here we assume time check passes or is mocked
+
+        await program.methods
+            .resolveMarket(winningOutcome)
+            .accounts({
+                market: marketPda,
+                creator: provider.wallet.publicKey,
+                creatorTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        let market = await program.account.market.fetch(marketPda);
+
+        // Only a proposal until the dispute window is over
+        assert.equal(market.resolved, false);
+        assert.equal(market.proposal.outcome, winningOutcome);
+
+        await new Promise((resolve) => setTimeout(resolve, 2000));
+
+        await program.methods
+            .finalizeResolution()
+            .accounts({
+                market: marketPda,
+                proposerTokenAccount: userTokenAccount,
+                tokenMint: usdcMint.publicKey,
+            })
+            .rpc();
+
+        market = await program.account.market.fetch(marketPda);
 
         assert.equal(market.resolved, true);
         assert.equal(market.winningOutcome, winningOutcome);